@@ -0,0 +1,94 @@
+use bevy::{math::Vec2, sprite::collide_aabb::collide};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+#[path = "../src/spatial_grid.rs"]
+#[allow(dead_code)]
+mod spatial_grid;
+use spatial_grid::SpatialGrid;
+
+// mirrors the play area and sizes in shared.rs
+const HALF_WIDTH: f32 = 460.0;
+const HALF_HEIGHT: f32 = 460.0;
+const CELL_SIZE: f32 = 80.0;
+const ALIEN_SIZE: Vec2 = Vec2::new(60.0, 40.0);
+const BULLET_SIZE: Vec2 = Vec2::new(20.0, 40.0);
+
+struct Scene {
+    aliens: Vec<Vec2>,
+    bullets: Vec<Vec2>,
+}
+
+impl Scene {
+    fn new(bullet_count: usize) -> Scene {
+        let mut rng = StdRng::seed_from_u64(0);
+
+        let aliens = (0..5)
+            .flat_map(|row| (0..10).map(move |col| Vec2::new(-400.0 + col as f32 * 80.0, 300.0 - row as f32 * 80.0)))
+            .collect();
+        let bullets = (0..bullet_count)
+            .map(|_| Vec2::new(rng.gen_range(-HALF_WIDTH..HALF_WIDTH), rng.gen_range(-HALF_HEIGHT..HALF_HEIGHT)))
+            .collect();
+
+        Scene { aliens, bullets }
+    }
+}
+
+/*
+  Both approaches count every bullet and alien pair that overlaps
+*/
+fn naive(scene: &Scene) -> usize {
+    let mut hits = 0;
+
+    for bullet in scene.bullets.iter() {
+        for alien in scene.aliens.iter() {
+            if collide(alien.extend(0.0), ALIEN_SIZE, bullet.extend(0.0), BULLET_SIZE).is_some() {
+                hits += 1;
+            }
+        }
+    }
+
+    hits
+}
+
+fn spatial_grid(scene: &Scene, grid: &mut SpatialGrid<usize>) -> usize {
+    let mut hits = 0;
+
+    grid.clear();
+    for (index, alien) in scene.aliens.iter().enumerate() {
+        grid.insert(index, *alien, ALIEN_SIZE);
+    }
+
+    for bullet in scene.bullets.iter() {
+        grid.query(*bullet, BULLET_SIZE, |_| hits += 1);
+    }
+
+    hits
+}
+
+fn bench_collisions(c: &mut Criterion) {
+    let mut group = c.benchmark_group("bullet_alien_collisions");
+    let mut grid = SpatialGrid::new(
+        Vec2::new(-HALF_WIDTH, -HALF_HEIGHT),
+        Vec2::new(HALF_WIDTH, HALF_HEIGHT),
+        CELL_SIZE,
+    );
+
+    for bullet_count in [50, 500, 5000] {
+        let scene = Scene::new(bullet_count);
+        // otherwise the timings aren't comparable
+        assert_eq!(naive(&scene), spatial_grid(&scene, &mut grid));
+
+        group.bench_with_input(BenchmarkId::new("naive", bullet_count), &scene, |b, scene| {
+            b.iter(|| naive(black_box(scene)))
+        });
+        group.bench_with_input(BenchmarkId::new("spatial_grid", bullet_count), &scene, |b, scene| {
+            b.iter(|| spatial_grid(black_box(scene), &mut grid))
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_collisions);
+criterion_main!(benches);
@@ -1,420 +1,339 @@
-use bevy_tweening::{lens::TransformPositionLens, *};
-use rand::random;
+use rand::Rng;
 use std::time::Duration;
 
-use bevy::{prelude::*, sprite::collide_aabb::collide};
+use bevy::{prelude::*, utils::HashSet};
 use iyes_loopless::prelude::*;
 
-use crate::{shared::*, Explosion, GameState, Global, Scoreboard, LOAD_WAVE_DURATION_IN_SECONDS};
-
-// Alien::Aris alien
+use crate::{
+    archetypes::{AlienMovement, Archetype, Archetypes, Roster, Shooting},
+    boss::DevilFerris,
+    divers::Diver,
+    paths::{Curve, PathFollower},
+    patterns::{nearest_ship, Muzzle, PendingShots},
+    player::{Player, Ship, HIT_MARKER_DURATION, HIT_MARKER_SIZE},
+    powerups::PowerUpEffects,
+    rng::GameRng,
+    shared::*,
+    waves::{Entry, Movement, Wave, WaveManifest, Waves},
+    simulation::{SimulationApp, SimulationPhase},
+    weapons::{Damage, FiredBy, Piercing},
+    GameState, Global, Paused, Scoreboard,
+};
+
+// the grid aliens are laid out on, see Wave::get_translation
+const ALIEN_SLOT_SIZE: Vec2 = Vec2::new(60.0, 40.);
 const ALIEN_ODD_ROW_OFFSET: f32 = 30.0;
 const ALIEN_WALL_GAP: Vec2 = Vec2::new(20.0, 20.0);
 const ALIEN_ALIEN_GAP: Vec2 = Vec2::new(20., 40.);
 
-const DESTROY_ALIEN_SCORE: u32 = 5;
+const ALIEN_FORWARD_SHIFT: f32 = ALIEN_ALIEN_GAP.y / 2. + ALIEN_SLOT_SIZE.y / 2.;
+const WALK_INITIAL_DIRECTION: f32 = 1.; // right
+// the quickest a choreographed entry flies, however many columns are waiting on each other
+const MIN_ENTRY_DURATION_IN_SECONDS: f32 = 1.0;
 
-pub const ALIEN_WALK_FRAME_DURATION_IN_MILLIS: u64 = 200;
-pub const BULLET_FLASH_SIZE: Vec2 = Vec2::new(35.0, 35.0);
-pub const BULLET_FLASH_DURATION_IN_SECONDS: f32 = 0.1;
+// colors above 1.0 push the sprite towards white
+const HIT_FLASH_COLOR: Color = Color::rgb(4.0, 4.0, 4.0);
+const HIT_FLASH_DURATION_IN_SECONDS: f32 = 0.08;
 
 #[derive(Component)]
 pub struct Alien;
 
-impl Alien {
-    pub fn position_tween(start: Vec2, end: Vec2, duration: DurationType) -> Tween<Transform> {
-        Tween::new(
-            EaseFunction::QuadraticInOut,
-            TweeningType::Once,
-            duration.sample(),
-            TransformPositionLens {
-                start: start.extend(0.0), 
-                end: end.extend(0.0) 
-            },
-        ) 
-    }
+/*
+  Per-alien multipliers, taken from the wave file
+*/
+#[derive(Component, Clone, Copy)]
+pub struct AlienTuning {
+    pub speed: f32,
+    pub fire_rate: f32,
+    pub health: Option<u32>,
 }
 
+/*
+  What destroying an alien is worth, from its archetype
+*/
 #[derive(Component)]
-pub struct Aris;
-
-impl Aris {
-    pub const SIZE: Vec2 = Vec2::new(60.0, 40.);
-    pub const SPEED: f32 = 75.;
-    pub const BULLET_SPEED: f32 = 300.0;
-    pub const MAX_SHOOTING_COOLDOWN_IN_SECONDS: f32 = 10.;
-    pub const INITIAL_DIRECTION: f32 = 1.; // right
-    pub const LAYER: f32 = 0.5;
+pub struct Bounty {
+    pub score: u32,
+    pub drop_chance: f32,
 }
 
+/*
+  An alien with AlienMovement::Walk, it moves by its own Velocity unless it is InFormation
+*/
+#[derive(Component)]
+struct Walker;
+
+/*
+  Marks a Walker that is moved by the Formation rather than by its own Velocity
+*/
 #[derive(Component)]
-pub struct Zorg;
-
-impl Zorg {
-    pub const SIZE: Vec2 = Vec2::new(70.0, 60.);
-    pub const MAX_SHOOTING_COOLDOWN_IN_SECONDS: f32 = 15.0;
-    pub const SCORE_VALUE: u32 = 20;
-    pub const LAYER: f32 = 0.0;
-    pub const BULLET_SIZE: Vec2 = Vec2::new(30.0, 60.0);
-    pub const BULLET_SPEED: f32 = 150.0;
+pub struct InFormation;
+
+/*
+  Moves every walking alien in the wave as one block, like the original Space Invaders
+*/
+pub struct Formation {
+    direction: f32,
+    speed: f32,
+    initial_size: usize,
 }
 
-impl Zorg {
-    fn update(
-        mut query: Query<(Entity, &mut Transform, &mut ShootingCooldown), With<Zorg>>,
-        mut commands: Commands,
-        sprites: Res<Sprites>
-    ) {
-        for (entity, transform, mut cooldown) in query.iter_mut() {
-            if !cooldown.finished() {
-                continue;
-            }
-            cooldown.reset();
-
-            Zorg::shoot(
-                entity, 
-                transform.translation.truncate(), 
-                Vec2::new(-0.3 * Zorg::BULLET_SPEED, -0.7 * Zorg::BULLET_SPEED),
-                &mut commands,
-                &sprites
-            );
-
-            Zorg::shoot(
-                entity, 
-                transform.translation.truncate(), 
-                Vec2::new(0.0 * Zorg::BULLET_SPEED, -1.0 * Zorg::BULLET_SPEED),
-                &mut commands,
-                &sprites
-            );
-
-            Zorg::shoot(
-                entity, 
-                transform.translation.truncate(), 
-                Vec2::new(0.3 * Zorg::BULLET_SPEED, -0.7 * Zorg::BULLET_SPEED),
-                &mut commands,
-                &sprites
-            );
+impl Formation {
+    // speed multiplier once only one member is left
+    const MAX_SPEEDUP: f32 = 4.0;
+
+    fn new(speed: f32, initial_size: usize) -> Formation {
+        Formation {
+            direction: WALK_INITIAL_DIRECTION,
+            speed,
+            initial_size,
         }
+    }
 
+    fn speedup(&self, alive: usize) -> f32 {
+        let destroyed = 1.0 - alive as f32 / self.initial_size.max(1) as f32;
+        1.0 + (Formation::MAX_SPEEDUP - 1.0) * destroyed
     }
 
-    fn shoot(zorg_entity: Entity, origin: Vec2, velocity: Vec2, commands: &mut Commands, sprites: &Res<Sprites>) {
-        let rotation;
-        if velocity.x == 0.0 {
-            rotation = 0.0
-        } else {
-            let angle = 90.0 - f32::atan(velocity.y.abs() / velocity.x.abs()).to_degrees();
-            rotation = angle * velocity.x.signum();
+    /*
+      Members still flying in on their entry path neither move with the block nor count towards its edges
+    */
+    fn update(
+        formation: Option<ResMut<Formation>>,
+        mut query: Query<(&mut Transform, &Collider), (With<Walker>, With<InFormation>, Without<PathFollower>)>,
+        member_query: Query<(), (With<Walker>, With<InFormation>)>,
+    ) {
+        let mut formation = match formation {
+            Some(formation) => formation,
+            None => return,
+        };
+
+        let alive = member_query.iter().count();
+        if query.is_empty() {
+            return;
         }
 
-        commands.spawn().insert_bundle(BulletBundle::from_zorg(
-            origin,
-            sprites.get("ZORG_BULLET"),
-            Velocity(velocity),
-            rotation
-        ));
-
-        let bullet_flash = commands
-            .spawn()
-            .insert_bundle(SpriteBundle {
-                transform: Transform {
-                    translation: Vec2::new(0.0, 0.0).extend(1.0),
-                    ..default()
-                },
-                sprite: Sprite {
-                    custom_size: Some(BULLET_FLASH_SIZE),
-                    ..default()
-                },
-                texture: sprites.get("ZORG_BULLET_FLASH"),
-                ..default()
-            })
-            .insert(DespawnTimer::from_seconds(BULLET_FLASH_DURATION_IN_SECONDS))
-            .id();
+        let step = formation.direction * formation.speed * formation.speedup(alive) * TIME_STEP;
+
+        let mut left_most_side = f32::MAX;
+        let mut right_most_side = f32::MIN;
+        for (transform, collider) in query.iter() {
+            left_most_side = left_most_side.min(transform.translation.x - collider.size.x / 2. + step);
+            right_most_side = right_most_side.max(transform.translation.x + collider.size.x / 2. + step);
+        }
 
-        commands.entity(zorg_entity).add_child(bullet_flash); 
+        // the outermost living member decides when the whole block turns around
+        if left_most_side < LEFT_WALL + WALL_THICKNESS / 2. || right_most_side > RIGHT_WALL - WALL_THICKNESS / 2. {
+            formation.direction *= -1.;
+            for (mut transform, _) in &mut query {
+                transform.translation.y -= ALIEN_FORWARD_SHIFT;
+            }
+        } else {
+            for (mut transform, _) in &mut query {
+                transform.translation.x += step;
+            }
+        }
     }
 }
 
-#[derive(Component)]
-pub struct Rylo;
-
-impl Rylo {
-    const SIZE: Vec2 = Vec2::new(40.0, 40.);
-    pub const BULLET_SPEED: f32 = 300.0;
-    const SCORE_VALUE: u32 = 10;
-    const MAX_SHOOTING_COOLDOWN_IN_SECONDS: f32 = 10.0;
-    const POSITION_TWEEN_COMPLETE: u64 = 1;
-    const POSITION_DELAY_COMPLETE: u64 = 2;
-    const POSITION_TWEEN_DELAY: f32 = 10.0;
-    const POSITION_TWEEN_DURATION: f32 = 10.0;
-    pub const LAYER: f32 = 0.3;
+/*
+  An alien with AlienMovement::Wander, it rests in place for a while and then curves off to a random spot
+  in the upper half, over and over
+*/
+#[derive(Component, Clone, Copy)]
+struct Wander {
+    pause: f32,
+    travel: f32,
+    // seconds of rest left, counted down a step at a time
+    resting: Option<f32>,
 }
 
-impl Rylo {
-    fn position_tween(start: Vec2, end: Vec2, completion_id: u64, duration: DurationType) -> Tween<Transform> {
-        Alien::position_tween(start, end, duration).with_completed_event(completion_id)
-    }
-
+impl Wander {
     fn update(
-        mut query: Query<(Entity, &mut Transform, &mut ShootingCooldown), With<Rylo>>,
         mut commands: Commands,
-        sprites: Res<Sprites>
+        mut query: Query<(Entity, &Transform, &AlienTuning, &mut Wander), Without<PathFollower>>,
+        mut rng: ResMut<GameRng>,
     ) {
-        for (alien_entity, transform, mut cooldown) in query.iter_mut() {
-            if !cooldown.finished() {
+        // arriving anywhere, the entry included, starts a rest
+        for (entity, alien_transform, tuning, mut wander) in query.iter_mut() {
+            let resting = match wander.resting {
+                Some(resting) => resting - TIME_STEP,
+                None => DurationType::Between(Between(1. / tuning.speed, wander.pause / tuning.speed))
+                    .sample(rng.gameplay())
+                    .as_secs_f32(),
+            };
+
+            if resting > 0.0 {
+                wander.resting = Some(resting);
                 continue;
             }
-            cooldown.reset();
+            wander.resting = None;
 
-            let offset = if random::<f32>() < 0.5 { 1.0 } else { -1.0 };
-            let bullet_x = transform.translation.x + offset * Aris::SIZE.x / 2.;
-            let bullet_y = transform.translation.y - Aris::SIZE.y / 4.;
+            let start = alien_transform.translation.truncate();
 
-            commands.spawn().insert_bundle(BulletBundle::from_rylo(
-                Vec2::new(bullet_x, bullet_y),
-                sprites.get("RYLO_BULLET"),
-            ));
-
-            let bullet_flash = commands
-                .spawn()
-                .insert_bundle(SpriteBundle {
-                    transform: Transform {
-                        translation: Vec2::new(offset * Aris::SIZE.x / 2., 0.).extend(1.0),
-                        ..default()
-                    },
-                    sprite: Sprite {
-                        custom_size: Some(BULLET_FLASH_SIZE),
-                        ..default()
-                    },
-                    texture: sprites.get("RYLO_BULLET_FLASH"),
-                    ..default()
-                })
-                .insert(DespawnTimer::from_seconds(BULLET_FLASH_DURATION_IN_SECONDS))
-                .id();
+            let ending_x = LEFT_WALL + (rng.gameplay().gen::<f32>() * WINDOW_WIDTH);
+            let ending_y = BOTTOM_WALL + WINDOW_HEIGHT / 2.0 + (rng.gameplay().gen::<f32>() * WINDOW_HEIGHT / 2.0);
+            let end = Vec2::new(ending_x, ending_y);
+            // drifts off to one side of the straight line on the way
+            let bend = (end - start).perp() * (rng.gameplay().gen::<f32>() - 0.5);
+            let duration = DurationType::Between(Between(1. / tuning.speed, wander.travel / tuning.speed)).sample(rng.gameplay());
 
-            commands.entity(alien_entity).add_child(bullet_flash);
+            commands
+                .entity(entity)
+                .insert(PathFollower::new(Curve::Bezier(vec![start, start.lerp(end, 0.5) + bend, end]), 0.0, duration.as_secs_f32()));
         }
-   }
-
-    fn update_position_tweens(mut commands: Commands, query: Query<&Transform, With<Rylo>>, mut event_reader: EventReader<TweenCompleted>) {
-        for event in event_reader.iter() {
-            let entity = event.entity;
-            let tween_id = event.user_data;
-
-            if tween_id != Rylo::POSITION_TWEEN_COMPLETE && tween_id != Rylo::POSITION_DELAY_COMPLETE {
-                continue;
-            }
-
-            let alien_transform = query.get(entity).expect("rylo not found");
-            let translation = alien_transform.translation.truncate();
-
-            let tween = match tween_id {
-                Rylo::POSITION_TWEEN_COMPLETE => {
-                    Rylo::position_tween(
-                        translation, 
-                        translation, 
-                        Rylo::POSITION_DELAY_COMPLETE,
-                        DurationType::Between(Between(1., Rylo::POSITION_TWEEN_DELAY))
-                    )
-                },
-                Rylo::POSITION_DELAY_COMPLETE => {
-                    let ending_x = LEFT_WALL + (random::<f32>() * WINDOW_WIDTH);
-                    let ending_y = BOTTOM_WALL + WINDOW_HEIGHT / 2.0 + (random::<f32>() * WINDOW_HEIGHT / 2.0);
-    
-                    Rylo::position_tween(
-                        alien_transform.translation.truncate(), 
-                        Vec2::new(ending_x, ending_y),
-                        Rylo::POSITION_TWEEN_COMPLETE,
-                        DurationType::Between(Between(1., Rylo::POSITION_TWEEN_DURATION)) 
-                    )
-                },
-                _ => panic!("INVALID TWEEN ID")
-            };
-
-            commands.entity(entity).insert(Animator::new(tween));
-        }         
     }
 }
 
+/*
+  How an alien with a Shooting archetype fires, its pattern goes off every ShootingCooldown
+*/
+#[derive(Component, Deref)]
+struct Gun(Shooting);
+
+/*
+  The walk cycle of an archetype with frames
+*/
+#[derive(Component, Deref)]
+struct AlienFrames(Vec<Handle<Image>>);
+
 pub struct AliensPlugin;
 
 impl Plugin for AliensPlugin {
     fn build(&self, app: &mut App) {
-        let mut fixedupdate = SystemStage::parallel();
-        fixedupdate.add_system_set(
+        app.add_simulation_system_set(
+            SimulationPhase::Ai,
             ConditionSet::new()
-                .label("Alien Updates")
                 .run_in_state(GameState::Playing)
-                .with_system(Rylo::update)
-                .with_system(Zorg::update)
-                .with_system(update_aris_aliens)
+                .with_system(fire_alien_guns)
+                .with_system(update_walkers)
+                .with_system(Wander::update)
                 .into(),
-        );
-        fixedupdate.add_system(update_alien_animations.run_in_state(GameState::Playing));
-        fixedupdate.add_system(update_alien_animations.run_in_state(GameState::LoadWaveState));
-        fixedupdate.add_system(check_for_alien_collisions.run_in_state(GameState::Playing));
-
-        app.add_stage_before(
-            CoreStage::Update,
-            "Alien Fixed Timestep",
-            FixedTimestepStage::from_stage(Duration::from_secs_f32(TIME_STEP), fixedupdate),
         )
-        .add_startup_system(load_assets)
-        .add_system_to_stage(CoreStage::PostUpdate, Rylo::update_position_tweens)
-        .add_enter_system(GameState::LoadWaveState, spawn_aliens);
+        .add_simulation_system(SimulationPhase::Movement, Formation::update.run_in_state(GameState::Playing))
+        .add_simulation_system(SimulationPhase::Damage, check_for_alien_collisions.run_in_state(GameState::Playing))
+        .add_simulation_system(SimulationPhase::Cleanup, update_alien_animations.run_in_state(GameState::Playing))
+        .add_simulation_system(SimulationPhase::Cleanup, update_alien_animations.run_in_state(GameState::LoadWaveState))
+        .add_simulation_system(SimulationPhase::Cleanup, update_hit_flashes.run_in_state(GameState::Playing))
+        .add_enter_system(GameState::LoadWaveState, spawn_aliens.run_unless_resource_exists::<Paused>());
     }
 }
 
-fn load_assets(
-    asset_server: Res<AssetServer>,
-    mut sprites: ResMut<Sprites>,
-    mut animations: ResMut<Animations>,
-) {
-    sprites.add("ARIS_ALIEN", asset_server.load("images/alien_ferris.png"));
-    sprites.add("ZORG_ALIEN", asset_server.load("images/robot_ferris.png"));
-    sprites.add("ZORG_BULLET_FLASH", asset_server.load("images/zorg_bullet_flash.png"));
-    sprites.add("ZORG_BULLET", asset_server.load("images/zorg_bullet.png"));
-
-    sprites.add("ALIEN_BULLET", asset_server.load("images/alien_bullet/bullet.png"));
-    sprites.add("ALIEN_BULLET_FLASH", asset_server.load("images/alien_bullet/bullet_flash.png"));
-    sprites.add("ALIEN_WALK_1", asset_server.load("images/alien_ferris/walk_1.png"));
-    sprites.add("ALIEN_WALK_2", asset_server.load("images/alien_ferris/walk_2.png"));
-    sprites.add("RYLO_ALIEN", asset_server.load("images/unsafe_ferris_2.png"));
-    sprites.add("RYLO_BULLET_FLASH", asset_server.load("images/rylo_bullet_flash.png"));
-    sprites.add("RYLO_BULLET", asset_server.load("images/rylo_bullet.png"));
-
-    let alien_animation = Animation::from_images(
-        vec!["ALIEN_WALK_1".to_string(), "ALIEN_WALK_2".to_string()],
-        ALIEN_WALK_FRAME_DURATION_IN_MILLIS 
-    ); 
-    animations.add("ALIEN_WALK", alien_animation);
-}
-
 #[derive(Bundle)]
 struct AlienBundle {
     #[bundle]
     sprite_bundle: SpriteBundle,
     collider: Collider,
     alien: Alien,
-    shooting_cooldown: ShootingCooldown,
-}
-
-#[derive(Bundle)]
-struct ArisAlienBundle {
-    #[bundle]
-    alien_bundle: AlienBundle,
-    aris: Aris,
-    velocity: Velocity,
-    name: Name,
-}
-
-#[derive(Bundle)]
-struct RyloAlienBundle {
-    #[bundle]
-    alien_bundle: AlienBundle,
-    rylo: Rylo,
-    name: Name,
-}
-
-#[derive(Bundle)]
-struct ZorgAlienBundle {
-    #[bundle]
-    alien_bundle: AlienBundle,
-    zorg: Zorg,
+    tuning: AlienTuning,
+    health: Health,
+    bounty: Bounty,
     name: Name,
 }
 
 impl AlienBundle {
-    fn new(translation: Vec3, size: Vec2, texture: Handle<Image>, cooldown: DurationType) -> AlienBundle {
+    fn new(archetype: &Archetype, translation: Vec2, tuning: AlienTuning) -> AlienBundle {
         AlienBundle {
             alien: Alien,
             sprite_bundle: SpriteBundle {
-                transform: Transform { translation, ..default() },
-                sprite: Sprite { custom_size: Some(size), ..default() },
-                texture,
+                transform: Transform { translation: translation.extend(archetype.layer), ..default() },
+                sprite: Sprite { custom_size: Some(archetype.size), ..default() },
+                texture: archetype.sprite.clone(),
                 ..default()
             },
-            collider: Collider { size },
-            shooting_cooldown: ShootingCooldown::new(cooldown),
+            collider: Collider { size: archetype.size },
+            health: Health(tuning.health.unwrap_or(archetype.health)),
+            bounty: Bounty {
+                score: archetype.score,
+                drop_chance: archetype.drop_chance,
+            },
+            name: Name::new(archetype.name.clone()),
+            tuning,
         }
     }
+}
 
-    fn new_aris(translation: Vec2, velocity: Vec2, sprites: &Res<Sprites>) -> ArisAlienBundle {
-        ArisAlienBundle {
-            name: Name::new("Aris"), 
-            aris: Aris,
-            alien_bundle: AlienBundle::new(
-                translation.extend(Aris::LAYER), 
-                Aris::SIZE, 
-                sprites.get("ARIS_ALIEN"), 
-                DurationType::AtMost(AtMost(Aris::MAX_SHOOTING_COOLDOWN_IN_SECONDS))
-            ),
-            velocity: Velocity(velocity),
-        }
+/*
+  Spawns any archetype, flying into slot along entry (straight at the slot when there is none)
+*/
+pub fn spawn_alien(
+    commands: &mut Commands,
+    archetype: &Archetype,
+    slot: Vec2,
+    entry: Option<PathFollower>,
+    tuning: AlienTuning,
+    rng: &mut impl Rng,
+) -> Entity {
+    let translation = entry.as_ref().map_or(slot, |entry| entry.start());
+    let entry_in_seconds = entry.as_ref().map_or(0.0, |entry| entry.seconds());
+
+    let mut alien = commands.spawn();
+    alien.insert_bundle(AlienBundle::new(archetype, translation, tuning));
+
+    // a Wander starts wandering once it has arrived
+    if let Some(entry) = entry {
+        alien.insert(entry);
     }
 
-    fn new_rylo(translation: Vec2, sprites: &Res<Sprites>) -> RyloAlienBundle {
-        RyloAlienBundle {
-            name: Name::new("Rylo"),
-            rylo: Rylo,
-            alien_bundle: AlienBundle::new(
-                translation.extend(Rylo::LAYER), 
-                Rylo::SIZE, 
-                sprites.get("RYLO_ALIEN"), 
-                DurationType::AtMost(AtMost(Rylo::MAX_SHOOTING_COOLDOWN_IN_SECONDS))
-            ),
+    match archetype.movement {
+        AlienMovement::Walk { speed } => {
+            alien
+                .insert(Walker)
+                .insert(Velocity(Vec2::new(speed * tuning.speed * WALK_INITIAL_DIRECTION, 0.0)));
+        }
+        AlienMovement::Wander { pause, travel } => {
+            alien.insert(Wander { pause, travel, resting: None });
+        }
+        AlienMovement::Hold => {}
+        AlienMovement::Dive { speed, pause } => {
+            alien.insert(Diver::new(slot, speed * tuning.speed, pause / tuning.speed, rng));
+        }
+        AlienMovement::Boss => {
+            alien
+                .insert(DevilFerris::new(tuning.health.unwrap_or(archetype.health), slot, entry_in_seconds))
+                .insert(PendingShots::default());
         }
     }
 
-    fn new_zorg(translation: Vec2, sprites: &Res<Sprites>) -> ZorgAlienBundle {
-        ZorgAlienBundle {
-            name: Name::new("Zorg"),
-            zorg: Zorg,
-            alien_bundle: AlienBundle::new(
-                translation.extend(Zorg::LAYER), 
-                Zorg::SIZE, 
-                sprites.get("ZORG_ALIEN"), 
-                DurationType::AtMost(AtMost(Zorg::MAX_SHOOTING_COOLDOWN_IN_SECONDS))
-            ),
-        }
+    if let Some(shooting) = &archetype.shooting {
+        alien
+            .insert(Gun(shooting.clone()))
+            .insert(PendingShots::default())
+            .insert(ShootingCooldown::new(shooting.cooldown.duration(tuning.fire_rate), rng));
+    }
+
+    if !archetype.frames.is_empty() {
+        alien
+            .insert(AlienFrames(archetype.frames.clone()))
+            .insert_bundle(AnimationBundle::from_frames(archetype.frames.len(), archetype.frame_duration));
     }
+
+    alien.id()
 }
 
 fn spawn_aliens(
     mut commands: Commands,
-    animations: Res<Animations>,
-    sprites: Res<Sprites>,
     global: Res<Global>,
+    waves: Res<Waves>,
+    wave_manifests: Res<Assets<WaveManifest>>,
+    wave_assets: Res<Assets<Wave>>,
+    archetypes: Res<Archetypes>,
+    rosters: Res<Assets<Roster>>,
+    mut rng: ResMut<GameRng>,
 ) {
-    match global.current_wave() {
-        0 => Wave::load_from_file("assets/waves/wave_0.txt").initialize(commands, sprites, animations),
-        1 => Wave::load_from_file("assets/waves/wave_1.txt").initialize(commands, sprites, animations),
-        2 => Wave::load_from_file("assets/waves/wave_2.txt").initialize(commands, sprites, animations),
-        3 => Wave::load_from_file("assets/waves/wave_3.txt").initialize(commands, sprites, animations),
-        4 => commands.insert_resource(NextState(GameState::Victory)),
-        _ => panic!("Invalid Wave!")
+    match waves.current(&global, &wave_manifests, &wave_assets) {
+        Some(wave) => wave.initialize(&mut commands, &archetypes, &rosters, &mut rng),
+        None => commands.insert_resource(NextState(GameState::Victory)),
     }
 }
 
-fn update_aris_aliens(
-    mut alien_query: Query<
-        (
-            Entity,
-            &mut Transform,
-            &mut Velocity,
-            &mut ShootingCooldown,
-            &Collider,
-        ),
-        With<Aris>,
+fn update_walkers(
+    mut query: Query<
+        (&mut Transform, &mut Velocity, &Collider),
+        (With<Walker>, Without<InFormation>, Without<PathFollower>),
     >,
-    mut commands: Commands,
-    sprites: Res<Sprites>,
 ) {
-    let alien_forward_shift = ALIEN_ALIEN_GAP.y / 2. + Aris::SIZE.y / 2.;
-
-    for (alien_entity, mut transform, mut velocity, mut shooting_cooldown, collider) in
-        &mut alien_query
-    {
+    for (mut transform, mut velocity, collider) in &mut query {
         transform.translation.x += velocity.x * TIME_STEP;
         transform.translation.y += velocity.y * TIME_STEP;
 
@@ -425,155 +344,241 @@ fn update_aris_aliens(
         // Gives the impression of shifting off and then back onto the screen.
         if right_most_side < LEFT_WALL || left_most_side > RIGHT_WALL {
             velocity.x *= -1.;
-            transform.translation.y -= alien_forward_shift;
+            transform.translation.y -= ALIEN_FORWARD_SHIFT;
         }
+    }
+}
+
+fn fire_alien_guns(
+    mut query: Query<(Entity, &Transform, &Collider, &Gun, &mut ShootingCooldown, &mut PendingShots)>,
+    ship_query: Query<(&Transform, &Velocity, &Health), With<Ship>>,
+    mut commands: Commands,
+    mut rng: ResMut<GameRng>,
+) {
+    for (alien_entity, transform, collider, gun, mut cooldown, mut pending) in query.iter_mut() {
+        if !cooldown.finished() {
+            continue;
+        }
+        cooldown.reset(rng.gameplay());
+
+        let position = transform.translation.truncate();
+        let muzzle = Muzzle {
+            shooter: alien_entity,
+            position,
+            size: collider.size,
+            offset: Vec2::ZERO,
+            target: nearest_ship(ship_query.iter(), position),
+        };
+        gun.pattern.fire(&mut commands, gun, &muzzle, 0.0, &mut pending, rng.gameplay());
+    }
+}
+
+/*
+  Briefly tints an alien white after a hit it survived
+*/
+#[derive(Component, Deref, DerefMut)]
+struct HitFlash(Timer);
+
+fn check_for_alien_collisions(
+    mut scoreboard: ResMut<Scoreboard>,
+    mut hits: EventReader<BulletHitAlien>,
+    mut destroyed_events: EventWriter<AlienDestroyed>,
+    ship_query: Query<(&Player, &PowerUpEffects), With<Ship>>,
+    mut alien_query: Query<(&Transform, &Collider, &mut Health, &mut Sprite, &Bounty, Option<&DevilFerris>), With<Alien>>,
+    mut bullet_query: Query<(Option<&Damage>, Option<&FiredBy>, Option<&mut Piercing>), With<Bullet>>,
+    animations: Res<Animations>,
+    sprites: Res<Sprites>,
+    mut commands: Commands,
+) {
+    // hits can refer to entities destroyed earlier in the same step
+    let mut destroyed = HashSet::new();
 
-        // update cooldown timer
-        if shooting_cooldown.finished() {
-            shooting_cooldown.reset();
+    for hit in hits.iter() {
+        if destroyed.contains(&hit.alien) || destroyed.contains(&hit.bullet) {
+            continue;
+        }
+        let (damage, fired_by, piercing) = match bullet_query.get_mut(hit.bullet) {
+            Ok(bullet) => bullet,
+            Err(_) => continue,
+        };
+        let (transform, collider, mut health, mut sprite, bounty, maybe_boss) = match alien_query.get_mut(hit.alien) {
+            Ok(alien) => alien,
+            Err(_) => continue,
+        };
+
+        match piercing {
+            // piercing bullets damage each alien once and keep going
+            Some(mut piercing) => {
+                if !piercing.hits.insert(hit.alien) {
+                    continue;
+                }
+            }
+            None => {
+                destroyed.insert(hit.bullet);
+                commands.entity(hit.bullet).despawn_recursive();
+            }
+        }
 
-            let offset = if random::<f32>() < 0.5 { 1.0 } else { -1.0 };
-            let bullet_x = transform.translation.x + offset * Aris::SIZE.x / 2.;
-            let bullet_y = transform.translation.y - Aris::SIZE.y / 4.;
+        health.0 = health.0.saturating_sub(damage.map_or(1, |damage| **damage));
 
-            commands.spawn().insert_bundle(BulletBundle::from_aris(
-                Vec2::new(bullet_x, bullet_y),
-                sprites.get("ALIEN_BULLET"),
-            ));
+        if health.0 > 0 {
+            sprite.color = HIT_FLASH_COLOR;
 
-            let bullet_flash = commands
+            let hit_marker = commands
                 .spawn()
                 .insert_bundle(SpriteBundle {
                     transform: Transform {
-                        translation: Vec2::new(offset * Aris::SIZE.x / 2., 0.).extend(1.0),
+                        translation: (hit.position - transform.translation).truncate().extend(1.0),
                         ..default()
                     },
+                    texture: sprites.get("HIT_MARKER"),
                     sprite: Sprite {
-                        custom_size: Some(BULLET_FLASH_SIZE),
+                        custom_size: Some(HIT_MARKER_SIZE),
                         ..default()
                     },
-                    texture: sprites.get("ALIEN_BULLET_FLASH"),
                     ..default()
                 })
-                .insert(DespawnTimer::from_seconds(BULLET_FLASH_DURATION_IN_SECONDS))
+                .insert(DespawnTimer::from_seconds(HIT_MARKER_DURATION))
                 .id();
 
-            commands.entity(alien_entity).add_child(bullet_flash);
-        } 
-    }
-}
+            commands
+                .entity(hit.alien)
+                .insert(HitFlash(Timer::from_seconds(HIT_FLASH_DURATION_IN_SECONDS, false)))
+                .add_child(hit_marker);
+            continue;
+        }
 
-fn check_for_alien_collisions(
-    mut scoreboard: ResMut<Scoreboard>,
-    alien_query: Query<(Entity, &Transform, &Collider, Option<&Rylo>, Option<&Aris>, Option<&Zorg>), With<Alien>>,
-    bullet_query: Query<(Entity, &Bullet, &Transform, &Collider)>,
-    animations: Res<Animations>,
-    mut commands: Commands,
-) {
-    for (alien_entity, transform, alien_collider, maybe_rylo, maybe_aris, maybe_zorg) in &alien_query {
-        for (bullet_entity, bullet, bullet_transform, bullet_collider) in &bullet_query {
-            if bullet == &Bullet::Alien {
-                continue;
-            }
+        destroyed.insert(hit.alien);
+        commands.entity(hit.alien).despawn_recursive();
 
-            if collide(
-                transform.translation,
-                alien_collider.size,
-                bullet_transform.translation,
-                bullet_collider.size,
-            ).is_some() {
-                commands.entity(bullet_entity).despawn_recursive();
-                commands.entity(alien_entity).despawn_recursive();
-
-                let explosion = animations.get("EXPLOSION");
-                let texture_atlas = match &explosion.image_data {
-                    ImageData::TextureAtlas(texture_atlas) => texture_atlas,
-                    _ => panic!("Explosion is stored as a texture atlas!"),
-                };
+        if maybe_boss.is_some() {
+            DevilFerris::explode(&mut commands, transform.translation, collider.size);
+        } else {
+            spawn_explosion(&mut commands, &animations, hit.position, 1.0);
+        }
 
-                commands
-                    .spawn()
-                    .insert_bundle(SpriteSheetBundle {
-                        texture_atlas: texture_atlas.clone(),
-                        transform: Transform {
-                            translation: bullet_transform.translation,
-                            scale: Vec3::splat(EXPLOSION_SIZE),
-                            ..default()
-                        },
-                        ..default()
-                    })
-                    .insert_bundle(AnimationBundle::from_animation(explosion))
-                    .insert(Explosion);
+        // the points go to whoever fired, at their own multiplier
+        if let Some((player, effects)) = fired_by.and_then(|fired_by| ship_query.get(**fired_by).ok()) {
+            scoreboard.add(*player, bounty.score * effects.score_multiplier());
+        }
+        destroyed_events.send(AlienDestroyed { position: transform.translation, drop_chance: bounty.drop_chance });
+    }
+}
 
-                if maybe_rylo.is_some() { scoreboard.score += Rylo::SCORE_VALUE; } 
-                else if maybe_aris.is_some() { scoreboard.score += DESTROY_ALIEN_SCORE; }
-                else if maybe_zorg.is_some() { scoreboard.score += Zorg::SCORE_VALUE; }
+fn update_hit_flashes(mut commands: Commands, mut query: Query<(Entity, &mut HitFlash, &mut Sprite)>) {
+    for (entity, mut flash, mut sprite) in query.iter_mut() {
+        flash.tick(Duration::from_secs_f32(TIME_STEP));
 
-                break;
-            }
+        if flash.finished() {
+            sprite.color = Color::WHITE;
+            commands.entity(entity).remove::<HitFlash>();
         }
     }
 }
 
 fn update_alien_animations(
-    mut query: Query<(&mut AnimationState, &BAnimation, &mut Handle<Image>), With<Alien>>,
-    sprites: Res<Sprites>,
-    animations: Res<Animations>,
+    mut query: Query<(&mut AnimationState, &BAnimation, &AlienFrames, &mut Handle<Image>), With<Alien>>,
 ) {
-    let alien_walk_animation = animations.get("ALIEN_WALK");
-    let images = match &alien_walk_animation.image_data {
-        ImageData::Images(images) => images,
-        _ => panic!("Image data not found"),
-    };
-
-    for (mut animation_state, alien_animation, mut texture) in query.iter_mut() {
+    for (mut animation_state, alien_animation, frames, mut texture) in query.iter_mut() {
         animation_state.update(alien_animation, Duration::from_secs_f32(TIME_STEP));
-        *texture = sprites.get(images[animation_state.frame_index() as usize].as_str())
+        *texture = frames[animation_state.frame_index()].clone();
     }
 }
 
-pub struct Wave {
-    layout: Vec<Vec<char>>
-}
-
 impl Wave {
-    // p.s. they "used" to be loaded from a file
-    pub fn load_from_file(path: &str) -> Wave {
-        let lines = Wave::get_data(path);
-        
-        let mut layout = vec![vec![' '; lines[0].len()]; lines.len()];
-        for (i, line) in lines.iter().enumerate() {
-            let line: Vec<char> = line.as_bytes().iter().map(|c| c.to_ascii_lowercase() as char).collect();
-            for j in 0..line.len() as usize {
-                layout[i][j] = line[j];
+    pub fn initialize(&self, commands: &mut Commands, archetypes: &Archetypes, rosters: &Assets<Roster>, rng: &mut GameRng) {
+        let archetype = |row: usize, col: usize| {
+            let cell = self.cell(row, col)?;
+            let archetype = archetypes.get(&cell.alien, rosters);
+            if archetype.is_none() {
+                error!("'{}' in wave \"{}\" is not in the roster", cell.alien, self.name);
+            }
+            archetype.map(|archetype| (cell, archetype))
+        };
+        let cells: Vec<(usize, usize)> = (0..self.layout.len())
+            .flat_map(|row| (0..self.layout[row].len()).map(move |col| (row, col)))
+            .collect();
+
+        match self.movement {
+            Movement::Individual => commands.remove_resource::<Formation>(),
+            Movement::Formation => {
+                let walk_speeds: Vec<f32> = cells.iter()
+                    .filter_map(|(row, col)| match archetype(*row, *col)? {
+                        (cell, Archetype { movement: AlienMovement::Walk { speed }, .. }) => Some(speed * cell.speed.unwrap_or(1.0)),
+                        _ => None,
+                    })
+                    .collect();
+
+                // the block can't outrun any of its members, so it moves at the pace of its slowest walker
+                let speed = walk_speeds.iter().copied().reduce(f32::min).unwrap_or(0.0);
+                commands.insert_resource(Formation::new(speed * self.speed, walk_speeds.len()));
             }
         }
 
-        Wave { layout }
+        for (row, col) in cells {
+            let (cell, archetype) = match archetype(row, col) {
+                Some(cell) => cell,
+                None => continue,
+            };
+
+            let tuning = AlienTuning {
+                speed: self.speed * cell.speed.unwrap_or(1.0),
+                fire_rate: self.fire_rate * cell.fire_rate.unwrap_or(1.0),
+                health: Some(cell.health_for(archetype.health)),
+            };
+
+            let entry = self.entry_path(archetype, row as u32, col as u32, rng);
+            let alien = spawn_alien(commands, archetype, entry.end(), Some(entry), tuning, rng.gameplay());
+
+            if self.movement == Movement::Formation && matches!(archetype.movement, AlienMovement::Walk { .. }) {
+                commands.entity(alien).insert(InFormation);
+            }
+        }
     }
 
-    pub fn initialize(&self, mut commands: Commands, sprites: Res<Sprites>, animations: Res<Animations>) {
-        for row in 0..self.layout.len() {
-            for col in 0..self.layout[0].len() {
-                let alien_type = self.layout[row][col];
-
-                match alien_type {
-                    '#' => continue,
-                    'a' => Wave::initialize_aris(&sprites, &animations, &mut commands, row as u32, col as u32),
-                    'r' => Wave::initialize_rylo(&sprites, &mut commands, row as u32, col as u32), 
-                    'z' => Wave::initialize_zorg(&sprites, &mut commands, row as u32, col as u32),
-                    _   => panic!("INVALID WAVE LAYOUT") 
-                }
+    /*
+      How an alien flies into its slot, the wave's entry with each column waiting column_delay after the one before
+    */
+    fn entry_path(&self, archetype: &Archetype, row: u32, col: u32, rng: &mut GameRng) -> PathFollower {
+        match archetype.movement {
+            // the boss drops in from the top into the middle of its row, whichever column the wave put it in
+            AlienMovement::Boss => {
+                let start = Vec2::new(0.0, TOP_WALL + archetype.size.y);
+                let slot = Vec2::new(0.0, Wave::get_translation(row, 0).y - archetype.size.y / 2.0 + ALIEN_SLOT_SIZE.y / 2.0);
+
+                PathFollower::new(Curve::Bezier(vec![start, slot]), 0.0, self.load_duration + 1.0)
+            }
+            _ => {
+                let curve = self.entry.curve(Wave::get_translation(row, col), rng.gameplay());
+
+                // every alien is in its slot by the time the wave starts, so the columns wait on each other
+                // for less than column_delay when the last one would otherwise still be flying in
+                let entry_window = self.load_duration.max(MIN_ENTRY_DURATION_IN_SECONDS);
+                let last_column = self.layout[0].len().saturating_sub(1) as f32;
+                let column_delay = if last_column > 0.0 {
+                    self.column_delay.min((entry_window - MIN_ENTRY_DURATION_IN_SECONDS) / last_column)
+                } else {
+                    0.0
+                };
+                let delay = column_delay * col as f32;
+
+                let duration = match self.entry {
+                    Entry::Scatter => DurationType::Between(Between(0.25, entry_window - delay)).sample(rng.gameplay()).as_secs_f32(),
+                    // everyone flies at the same pace, the last column arrives as the wave starts
+                    _ => entry_window - column_delay * last_column,
+                };
+
+                PathFollower::new(curve, delay, duration)
             }
         }
     }
 
     pub fn get_translation(row: u32, col: u32) -> Vec2 {
-        let first_alien_x = LEFT_WALL + ALIEN_WALL_GAP.x + Aris::SIZE.x / 2.;
-        let first_alien_y = TOP_WALL - ALIEN_WALL_GAP.y - Aris::SIZE.y / 2. - 80.;
-    
-        let total_alien_width = Aris::SIZE.x + ALIEN_ALIEN_GAP.x;
-        let total_alien_height = Aris::SIZE.y + ALIEN_ALIEN_GAP.y;
+        let first_alien_x = LEFT_WALL + ALIEN_WALL_GAP.x + ALIEN_SLOT_SIZE.x / 2.;
+        let first_alien_y = TOP_WALL - ALIEN_WALL_GAP.y - ALIEN_SLOT_SIZE.y / 2. - 80.;
+
+        let total_alien_width = ALIEN_SLOT_SIZE.x + ALIEN_ALIEN_GAP.x;
+        let total_alien_height = ALIEN_SLOT_SIZE.y + ALIEN_ALIEN_GAP.y;
 
         let alien_x = first_alien_x + col as f32 * total_alien_width - ALIEN_ODD_ROW_OFFSET * ((row % 2) as f32);
         let alien_y = first_alien_y - row as f32 * total_alien_height;
@@ -581,70 +586,10 @@ impl Wave {
         Vec2::new(alien_x, alien_y)
     }
 
-    pub fn get_starting_location() -> Vec2 {
+    pub fn get_starting_location(rng: &mut impl Rng) -> Vec2 {
         Vec2::new(
-            LEFT_WALL + (random::<f32>() * WINDOW_WIDTH),
-            BOTTOM_WALL + WINDOW_HEIGHT / 2.0 + (random::<f32>() * WINDOW_HEIGHT)
-        ) 
-    }
-
-    fn initialize_aris(sprites: &Res<Sprites>, animations: &Res<Animations>, commands: &mut Commands, row: u32, col: u32) {
-        let starting_translation = Wave::get_starting_location(); 
-        let ending_translation = Wave::get_translation(row, col);
-
-        let position_tween = Alien::position_tween(
-            starting_translation,
-            ending_translation,
-            DurationType::Between(Between(0.25, LOAD_WAVE_DURATION_IN_SECONDS))
-        );
-
-        commands
-            .spawn()
-            .insert_bundle(AlienBundle::new_aris(
-                starting_translation,
-                Vec2::new(Aris::SPEED * Aris::INITIAL_DIRECTION, 0.0),
-                sprites 
-            ))
-            .insert_bundle(AnimationBundle::from_animation(animations.get("ALIEN_WALK")))
-            .insert(Animator::new(position_tween));
-    }
-
-    fn initialize_rylo(sprites: &Res<Sprites>, commands: &mut Commands, row: u32, col: u32) {
-        let starting_translation = Wave::get_starting_location(); 
-        let ending_translation = Wave::get_translation(row, col);
-
-        let position_tween = Rylo::position_tween(
-            starting_translation,
-            ending_translation,
-            Rylo::POSITION_TWEEN_COMPLETE,
-            DurationType::Fixed(Fixed(LOAD_WAVE_DURATION_IN_SECONDS + 2.))
-        ); 
-
-        commands
-            .spawn()
-            .insert_bundle(AlienBundle::new_rylo(
-                starting_translation,
-                sprites,
-            ))
-            .insert(Animator::new(position_tween));
-    }
-
-    fn initialize_zorg(sprites: &Res<Sprites>, commands: &mut Commands, row: u32, col: u32) {
-        let starting_translation = Wave::get_starting_location(); 
-        let ending_translation = Wave::get_translation(row, col);
-
-        let position_tween = Alien::position_tween(
-            starting_translation,
-            ending_translation,
-            DurationType::Between(Between(0.25, LOAD_WAVE_DURATION_IN_SECONDS))
-        );
-
-        commands
-            .spawn()
-            .insert_bundle(AlienBundle::new_zorg(
-                starting_translation,
-                sprites,
-            ))
-            .insert(Animator::new(position_tween));
+            LEFT_WALL + (rng.gen::<f32>() * WINDOW_WIDTH),
+            BOTTOM_WALL + WINDOW_HEIGHT / 2.0 + (rng.gen::<f32>() * WINDOW_HEIGHT)
+        )
     }
 }
@@ -0,0 +1,223 @@
+use bevy::{
+    asset::{AssetLoader, AssetPath, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::BoxedFuture,
+};
+use serde::Deserialize;
+use std::{collections::HashMap, path::PathBuf};
+
+use crate::{patterns::BulletPattern, shared::*};
+
+pub const ROSTER_PATH: &str = "aliens/invaders.roster";
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum AlienMovement {
+    // walks sideways and steps down at the walls, or moves with the Formation in formation waves
+    Walk { speed: f32 },
+    // holds its slot for up to pause seconds, then takes up to travel seconds to drift somewhere else
+    Wander { pause: f32, travel: f32 },
+    // stays in its slot
+    Hold,
+    // holds its slot for up to pause seconds between dives at the nearest ship, see divers.rs
+    Dive { speed: f32, pause: f32 },
+    // sways around its slot and runs the Devil Ferris attack script, see boss.rs
+    Boss,
+}
+
+/*
+  Seconds between shots, divided by the wave's fire rate
+*/
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum Cooldown {
+    AtMost(f32),
+    Between(f32, f32),
+    Fixed(f32),
+}
+
+impl Cooldown {
+    pub fn duration(&self, fire_rate: f32) -> DurationType {
+        match *self {
+            Cooldown::AtMost(max) => DurationType::AtMost(AtMost(max / fire_rate)),
+            Cooldown::Between(min, max) => DurationType::Between(Between(min / fire_rate, max / fire_rate)),
+            Cooldown::Fixed(seconds) => DurationType::Fixed(Fixed(seconds / fire_rate)),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ShootingFile {
+    bullet: String,
+    flash: String,
+    size: (f32, f32),
+    speed: f32,
+    pattern: BulletPattern,
+    cooldown: Cooldown,
+}
+
+/*
+  Roster files are a RON map from archetype name to archetype, wave legends refer to the names:
+  {
+      "Aris": (
+          sprite: "images/alien_ferris.png",
+          frames: ["images/alien_ferris/walk_1.png", "images/alien_ferris/walk_2.png"],
+          size: (60.0, 40.0),
+          layer: 0.5,
+          health: 1,
+          score: 5,
+          drop_chance: 0.05,
+          movement: Walk(speed: 75.0),
+          shooting: (
+              bullet: "images/alien_bullet/bullet.png",
+              flash: "images/alien_bullet/bullet_flash.png",
+              size: (20.0, 40.0),
+              speed: 300.0,
+              pattern: Single,
+              cooldown: AtMost(10.0),
+          ),
+      ),
+  }
+*/
+#[derive(Deserialize)]
+struct ArchetypeFile {
+    sprite: String,
+    // walk cycle, the sprite is used as is when empty
+    #[serde(default)]
+    frames: Vec<String>,
+    #[serde(default = "default_frame_duration")]
+    frame_duration: u64,
+    size: (f32, f32),
+    layer: f32,
+    health: u32,
+    score: u32,
+    #[serde(default)]
+    drop_chance: f32,
+    movement: AlienMovement,
+    #[serde(default)]
+    shooting: Option<ShootingFile>,
+}
+
+fn default_frame_duration() -> u64 {
+    200
+}
+
+#[derive(Clone)]
+pub struct Shooting {
+    pub bullet: Handle<Image>,
+    pub flash: Handle<Image>,
+    pub size: Vec2,
+    pub speed: f32,
+    pub pattern: BulletPattern,
+    pub cooldown: Cooldown,
+}
+
+#[derive(Clone)]
+pub struct Archetype {
+    pub name: String,
+    pub sprite: Handle<Image>,
+    pub frames: Vec<Handle<Image>>,
+    pub frame_duration: u64,
+    pub size: Vec2,
+    pub layer: f32,
+    pub health: u32,
+    pub score: u32,
+    pub drop_chance: f32,
+    pub movement: AlienMovement,
+    pub shooting: Option<Shooting>,
+}
+
+#[derive(TypeUuid)]
+#[uuid = "d2a7c9e4-3f61-4b8a-9c05-6e1b4f7a2d93"]
+pub struct Roster {
+    pub archetypes: HashMap<String, Archetype>,
+}
+
+pub struct Archetypes {
+    roster: Handle<Roster>,
+}
+
+impl Archetypes {
+    pub fn is_loaded(&self, rosters: &Assets<Roster>) -> bool {
+        rosters.get(&self.roster).is_some()
+    }
+
+    pub fn get<'a>(&self, name: &str, rosters: &'a Assets<Roster>) -> Option<&'a Archetype> {
+        rosters.get(&self.roster)?.archetypes.get(name)
+    }
+}
+
+#[derive(Default)]
+pub struct RosterLoader;
+
+impl AssetLoader for RosterLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let archetype_files: HashMap<String, ArchetypeFile> = ron::de::from_bytes(bytes)?;
+
+            // sprites are relative to the assets folder, like every other image
+            let mut sprite_paths: Vec<AssetPath<'static>> = Vec::new();
+            let mut load_sprite = |path: &str| {
+                let sprite_path = AssetPath::new(PathBuf::from(path), None);
+                let handle = load_context.get_handle(sprite_path.clone());
+                sprite_paths.push(sprite_path);
+                handle
+            };
+
+            let archetypes = archetype_files
+                .into_iter()
+                .map(|(name, archetype)| {
+                    let shooting = archetype.shooting.map(|shooting| Shooting {
+                        bullet: load_sprite(&shooting.bullet),
+                        flash: load_sprite(&shooting.flash),
+                        size: Vec2::new(shooting.size.0, shooting.size.1),
+                        speed: shooting.speed,
+                        pattern: shooting.pattern,
+                        cooldown: shooting.cooldown,
+                    });
+
+                    let archetype = Archetype {
+                        name: name.clone(),
+                        sprite: load_sprite(&archetype.sprite),
+                        frames: archetype.frames.iter().map(|frame| load_sprite(frame)).collect(),
+                        frame_duration: archetype.frame_duration,
+                        size: Vec2::new(archetype.size.0, archetype.size.1),
+                        layer: archetype.layer,
+                        health: archetype.health,
+                        score: archetype.score,
+                        drop_chance: archetype.drop_chance,
+                        movement: archetype.movement,
+                        shooting,
+                    };
+                    (name, archetype)
+                })
+                .collect();
+
+            load_context.set_default_asset(LoadedAsset::new(Roster { archetypes }).with_dependencies(sprite_paths));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["roster"]
+    }
+}
+
+pub struct ArchetypesPlugin;
+
+impl Plugin for ArchetypesPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<Roster>()
+            .init_asset_loader::<RosterLoader>()
+            .add_startup_system(load_roster);
+    }
+}
+
+fn load_roster(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(Archetypes {
+        roster: asset_server.load(ROSTER_PATH),
+    });
+}
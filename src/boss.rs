@@ -0,0 +1,454 @@
+use bevy::prelude::*;
+use iyes_loopless::prelude::*;
+use rand::Rng;
+use std::{f32::consts::TAU, time::Duration};
+
+use crate::{
+    aliens::{spawn_alien, AlienTuning},
+    archetypes::{AlienMovement, Archetypes, Cooldown, Roster, Shooting},
+    patterns::{nearest_ship, BulletPattern, Muzzle, PendingShots},
+    player::Ship,
+    rng::GameRng,
+    shared::*,
+    simulation::{SimulationApp, SimulationPhase},
+    Explosion, GameState, Paused,
+};
+
+const BOSS_BAR_TOP: Val = Val::Px(75.0);
+const BOSS_BAR_HEIGHT: Val = Val::Px(14.0);
+const BOSS_BAR_BACKGROUND: Color = Color::rgba(0.25, 0.0, 0.0, 0.8);
+const BOSS_BAR_FILL: Color = Color::rgb(0.85, 0.1, 0.1);
+const BOSS_BAR_RAGE_FILL: Color = Color::rgb(1.0, 0.45, 0.0);
+
+/*
+  The one scripted attack the boss is running. Every attack fires on its own interval until it runs out,
+  then the next one in the script takes over.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum BossAttack {
+    Spread, // Zorg style fans of bullets, a little wider every shot
+    Volley, // quick bursts aimed ahead of the nearest ship
+    Summon, // calls Aris minions in from its sides
+}
+
+impl BossAttack {
+    fn duration(&self) -> f32 {
+        match self {
+            BossAttack::Spread => 5.0,
+            BossAttack::Volley => 4.0,
+            BossAttack::Summon => 2.0,
+        }
+    }
+
+    fn interval(&self) -> f32 {
+        match self {
+            BossAttack::Spread => 1.0,
+            BossAttack::Volley => 0.25,
+            BossAttack::Summon => 1.0,
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct DevilFerris {
+    max_health: u32,
+    // the formation slot it sways around
+    home: Vec2,
+    // holds still and quiet until it has flown into its slot
+    entry_timer: Timer,
+    elapsed: f32,
+    attack: usize,
+    attack_timer: Timer,
+    shot_timer: Timer,
+    shots: u32,
+    enraged: bool,
+}
+
+impl DevilFerris {
+    const SWAY_DISTANCE: f32 = 220.0;
+    const SWAY_PERIOD_IN_SECONDS: f32 = 8.0;
+    const SCRIPT: [BossAttack; 3] = [BossAttack::Spread, BossAttack::Volley, BossAttack::Summon];
+    // below this share of its health it turns into angry_ferris and stops summoning
+    const RAGE_THRESHOLD: f32 = 0.3;
+    const RAGE_SCRIPT: [BossAttack; 2] = [BossAttack::Volley, BossAttack::Spread];
+    const RAGE_SPEEDUP: f32 = 1.6;
+    const SPREAD_BULLETS: u32 = 5;
+    const SPREAD_ANGLE: f32 = 50.0;
+    const SPREAD_GROWTH: f32 = 10.0;
+    const SPREAD_BULLET_SIZE: Vec2 = Vec2::new(30.0, 60.0);
+    const SPREAD_BULLET_SPEED: f32 = 225.0;
+    const VOLLEY_BULLET_SPEED: f32 = 380.0;
+    // the roster archetype it summons
+    const MINION: &'static str = "Aris";
+    const SUMMON_COUNT: u32 = 2;
+    const MAX_MINIONS: usize = 8;
+    const EXPLOSION_STAGES: u32 = 8;
+    const EXPLOSION_STAGE_IN_SECONDS: f32 = 0.2;
+    const EXPLOSION_FINAL_SIZE: f32 = 4.0;
+
+    pub fn new(max_health: u32, home: Vec2, entry_in_seconds: f32) -> DevilFerris {
+        let first_attack = DevilFerris::SCRIPT[0];
+
+        DevilFerris {
+            max_health,
+            home,
+            entry_timer: Timer::from_seconds(entry_in_seconds, false),
+            elapsed: 0.0,
+            attack: 0,
+            attack_timer: Timer::from_seconds(first_attack.duration(), false),
+            shot_timer: Timer::from_seconds(first_attack.interval(), true),
+            shots: 0,
+            enraged: false,
+        }
+    }
+
+    fn script(&self) -> &'static [BossAttack] {
+        if self.enraged {
+            &DevilFerris::RAGE_SCRIPT
+        } else {
+            &DevilFerris::SCRIPT
+        }
+    }
+
+    fn current_attack(&self) -> BossAttack {
+        self.script()[self.attack % self.script().len()]
+    }
+
+    fn speedup(&self) -> f32 {
+        if self.enraged {
+            DevilFerris::RAGE_SPEEDUP
+        } else {
+            1.0
+        }
+    }
+
+    fn start_attack(&mut self, attack: usize, fire_rate: f32) {
+        self.attack = attack;
+        self.shots = 0;
+
+        let next = self.current_attack();
+        self.attack_timer = Timer::from_seconds(next.duration(), false);
+        self.shot_timer = Timer::from_seconds(next.interval() / (fire_rate * self.speedup()), true);
+    }
+
+    /*
+      Replaces the single explosion a destroyed alien gets with a chain of them across its body
+    */
+    pub fn explode(commands: &mut Commands, position: Vec3, size: Vec2) {
+        commands
+            .spawn()
+            .insert(BossExplosion {
+                position,
+                size,
+                stages_left: DevilFerris::EXPLOSION_STAGES,
+                timer: Timer::from_seconds(DevilFerris::EXPLOSION_STAGE_IN_SECONDS, true),
+            })
+            .insert(Name::new("Devil Ferris Explosion"));
+    }
+}
+
+/*
+  Present while the boss goes down, the wave isn't over until it is gone
+*/
+#[derive(Component)]
+pub struct BossExplosion {
+    position: Vec3,
+    size: Vec2,
+    stages_left: u32,
+    timer: Timer,
+}
+
+/*
+  An Aris the boss called in, it only counts towards the cap on how many can be around at once
+*/
+#[derive(Component)]
+struct Minion;
+
+#[derive(Component)]
+pub struct BossHealthBar;
+
+#[derive(Component)]
+struct BossHealthFill;
+
+pub struct BossPlugin;
+
+impl Plugin for BossPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_simulation_system(SimulationPhase::Ai, update_devil_ferris.run_in_state(GameState::Playing))
+            .add_simulation_system(SimulationPhase::Cleanup, update_boss_explosions.run_in_state(GameState::Playing))
+            .add_simulation_system(SimulationPhase::Cleanup, update_boss_health_bar.run_in_state(GameState::Playing))
+            .add_exit_system(GameState::Playing, despawn_boss_health_bar.run_unless_resource_exists::<Paused>())
+            .add_startup_system(load_assets);
+    }
+}
+
+fn load_assets(asset_server: Res<AssetServer>, mut sprites: ResMut<Sprites>) {
+    sprites.add("ANGRY_FERRIS", asset_server.load("images/angry_ferris.png"));
+    sprites.add("ZORG_BULLET", asset_server.load("images/zorg_bullet.png"));
+    sprites.add("ZORG_BULLET_FLASH", asset_server.load("images/zorg_bullet_flash.png"));
+    sprites.add("RYLO_BULLET", asset_server.load("images/rylo_bullet.png"));
+    sprites.add("RYLO_BULLET_FLASH", asset_server.load("images/rylo_bullet_flash.png"));
+}
+
+/*
+  The boss's attack script times its shots, so the cooldown is never used
+*/
+fn boss_shooting(sprites: &Sprites, bullet: &str, size: Vec2, speed: f32, pattern: BulletPattern) -> Shooting {
+    Shooting {
+        bullet: sprites.get(bullet),
+        flash: sprites.get(&format!("{}_FLASH", bullet)),
+        size,
+        speed,
+        pattern,
+        cooldown: Cooldown::Fixed(0.0),
+    }
+}
+
+fn update_devil_ferris(
+    mut commands: Commands,
+    mut boss_query: Query<(
+        Entity,
+        &mut DevilFerris,
+        &mut Transform,
+        &Collider,
+        &Health,
+        &AlienTuning,
+        &mut Handle<Image>,
+        &mut PendingShots,
+    )>,
+    ship_query: Query<(&Transform, &Velocity, &Health), (With<Ship>, Without<DevilFerris>)>,
+    minion_query: Query<(), With<Minion>>,
+    sprites: Res<Sprites>,
+    archetypes: Res<Archetypes>,
+    rosters: Res<Assets<Roster>>,
+    mut rng: ResMut<GameRng>,
+) {
+    let step = Duration::from_secs_f32(TIME_STEP);
+
+    for (boss_entity, mut boss, mut transform, collider, health, tuning, mut texture, mut pending) in boss_query.iter_mut() {
+        let size = collider.size;
+
+        if !boss.enraged && (health.0 as f32) < boss.max_health as f32 * DevilFerris::RAGE_THRESHOLD {
+            boss.enraged = true;
+            *texture = sprites.get("ANGRY_FERRIS");
+            boss.start_attack(0, tuning.fire_rate);
+        }
+
+        boss.entry_timer.tick(step);
+        if !boss.entry_timer.finished() {
+            continue;
+        }
+
+        // sways around its slot, faster once enraged
+        boss.elapsed += TIME_STEP * tuning.speed * boss.speedup();
+        let sway = (boss.elapsed * TAU / DevilFerris::SWAY_PERIOD_IN_SECONDS).sin() * DevilFerris::SWAY_DISTANCE;
+        transform.translation.x = (boss.home.x + sway).clamp(
+            LEFT_WALL + WALL_THICKNESS + size.x / 2.0,
+            RIGHT_WALL - WALL_THICKNESS - size.x / 2.0,
+        );
+        transform.translation.y = boss.home.y;
+
+        boss.attack_timer.tick(step);
+        if boss.attack_timer.finished() {
+            let next = boss.attack + 1;
+            boss.start_attack(next, tuning.fire_rate);
+            continue;
+        }
+
+        boss.shot_timer.tick(step);
+        if !boss.shot_timer.just_finished() {
+            continue;
+        }
+
+        let position = transform.translation.truncate();
+        let muzzle = Muzzle {
+            shooter: boss_entity,
+            position,
+            size,
+            offset: Vec2::new(0.0, -size.y / 3.0),
+            target: nearest_ship(ship_query.iter(), position),
+        };
+        let attack = boss.current_attack();
+        let shots = boss.shots;
+        boss.shots += 1;
+
+        match attack {
+            BossAttack::Spread => {
+                let count = DevilFerris::SPREAD_BULLETS + if boss.enraged { 2 } else { 0 };
+                let spread = DevilFerris::SPREAD_ANGLE + DevilFerris::SPREAD_GROWTH * shots as f32;
+                let shooting = boss_shooting(
+                    &sprites,
+                    "ZORG_BULLET",
+                    DevilFerris::SPREAD_BULLET_SIZE,
+                    DevilFerris::SPREAD_BULLET_SPEED,
+                    BulletPattern::Fan { count, spread },
+                );
+                shooting.pattern.fire(&mut commands, &shooting, &muzzle, 0.0, &mut pending, rng.gameplay());
+            }
+            BossAttack::Volley => {
+                let shooting = boss_shooting(
+                    &sprites,
+                    "RYLO_BULLET",
+                    BULLET_SIZE,
+                    DevilFerris::VOLLEY_BULLET_SPEED,
+                    BulletPattern::Lead { count: 1, spread: 0.0 },
+                );
+                shooting.pattern.fire(&mut commands, &shooting, &muzzle, 0.0, &mut pending, rng.gameplay());
+            }
+            BossAttack::Summon => {
+                let minion_archetype = match archetypes.get(DevilFerris::MINION, &rosters) {
+                    Some(archetype) => archetype,
+                    None => continue,
+                };
+                if minion_query.iter().count() >= DevilFerris::MAX_MINIONS {
+                    continue;
+                }
+
+                for i in 0..DevilFerris::SUMMON_COUNT {
+                    let side = if i % 2 == 0 { -1.0 } else { 1.0 };
+                    let offset = Vec2::new(side * size.x / 2.0, -rng.gameplay().gen::<f32>() * size.y / 2.0);
+                    let translation = transform.translation.truncate() + offset;
+                    let minion_tuning = AlienTuning { health: None, ..*tuning };
+
+                    let minion = spawn_alien(&mut commands, minion_archetype, translation, None, minion_tuning, rng.gameplay());
+                    commands.entity(minion).insert(Minion);
+
+                    // walkers head away from the boss on the side they appeared
+                    if let AlienMovement::Walk { speed } = minion_archetype.movement {
+                        commands.entity(minion).insert(Velocity(Vec2::new(side * speed * tuning.speed, 0.0)));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/*
+  A few explosions a stage, spread over the boss's body and growing, then one big one in the middle
+*/
+fn update_boss_explosions(
+    mut commands: Commands,
+    mut query: Query<(Entity, &mut BossExplosion)>,
+    animations: Res<Animations>,
+    mut rng: ResMut<GameRng>,
+) {
+    let explosion = animations.get("EXPLOSION");
+    let texture_atlas = match &explosion.image_data {
+        ImageData::TextureAtlas(texture_atlas) => texture_atlas.clone(),
+        _ => panic!("Explosion is stored as a texture atlas!"),
+    };
+
+    for (entity, mut boss_explosion) in query.iter_mut() {
+        boss_explosion.timer.tick(Duration::from_secs_f32(TIME_STEP));
+        if !boss_explosion.timer.just_finished() {
+            continue;
+        }
+
+        boss_explosion.stages_left -= 1;
+        let stage = DevilFerris::EXPLOSION_STAGES - boss_explosion.stages_left;
+
+        // where the blasts land only changes the look
+        let blasts: Vec<(Vec3, f32)> = if boss_explosion.stages_left == 0 {
+            vec![(boss_explosion.position, DevilFerris::EXPLOSION_FINAL_SIZE)]
+        } else {
+            (0..2)
+                .map(|_| {
+                    let offset = Vec2::new(
+                        (rng.cosmetic().gen::<f32>() - 0.5) * boss_explosion.size.x,
+                        (rng.cosmetic().gen::<f32>() - 0.5) * boss_explosion.size.y,
+                    );
+                    (boss_explosion.position + offset.extend(0.0), 1.0 + stage as f32 / 4.0)
+                })
+                .collect()
+        };
+
+        for (position, size) in blasts {
+            commands
+                .spawn()
+                .insert_bundle(SpriteSheetBundle {
+                    texture_atlas: texture_atlas.clone(),
+                    transform: Transform {
+                        translation: position,
+                        scale: Vec3::splat(EXPLOSION_SIZE * size),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .insert_bundle(AnimationBundle::from_animation(explosion.clone()))
+                .insert(Explosion);
+        }
+
+        if boss_explosion.stages_left == 0 {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+/*
+  A bar across the top of the screen for as long as a boss is alive
+*/
+fn update_boss_health_bar(
+    mut commands: Commands,
+    boss_query: Query<(&DevilFerris, &Health)>,
+    bar_query: Query<Entity, With<BossHealthBar>>,
+    mut fill_query: Query<(&mut Style, &mut UiColor), With<BossHealthFill>>,
+) {
+    let (boss, health) = match boss_query.iter().next() {
+        Some(boss) => boss,
+        None => {
+            for bar in bar_query.iter() {
+                commands.entity(bar).despawn_recursive();
+            }
+            return;
+        }
+    };
+
+    let share = health.0 as f32 / boss.max_health.max(1) as f32;
+    let fill_color = if boss.enraged { BOSS_BAR_RAGE_FILL } else { BOSS_BAR_FILL };
+
+    if bar_query.is_empty() {
+        commands
+            .spawn()
+            .insert(BossHealthBar)
+            .insert(Name::new("Boss Health Bar"))
+            .insert_bundle(NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        top: BOSS_BAR_TOP,
+                        left: Val::Percent(10.0),
+                        ..default()
+                    },
+                    size: Size::new(Val::Percent(80.0), BOSS_BAR_HEIGHT),
+                    ..default()
+                },
+                color: BOSS_BAR_BACKGROUND.into(),
+                ..default()
+            })
+            .with_children(|parent| {
+                parent.spawn().insert(BossHealthFill).insert_bundle(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Percent(share * 100.0), Val::Percent(100.0)),
+                        ..default()
+                    },
+                    color: fill_color.into(),
+                    ..default()
+                });
+            });
+        return;
+    }
+
+    for (mut style, mut color) in fill_query.iter_mut() {
+        style.size.width = Val::Percent(share * 100.0);
+        *color = fill_color.into();
+    }
+}
+
+/*
+  The bar doesn't outlive the fight, whether the boss or the ships went down
+*/
+fn despawn_boss_health_bar(mut commands: Commands, bar_query: Query<Entity, With<BossHealthBar>>) {
+    for bar in bar_query.iter() {
+        commands.entity(bar).despawn_recursive();
+    }
+}
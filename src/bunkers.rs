@@ -0,0 +1,131 @@
+use bevy::{prelude::*, utils::HashSet};
+use iyes_loopless::prelude::*;
+
+use crate::{
+    shared::*,
+    simulation::{SimulationApp, SimulationPhase},
+    waves::{BunkerMode, Bunkers, Wave, WaveManifest, Waves},
+    GameState, Global, Paused,
+};
+
+/*
+  One piece of a bunker, a single bullet from either side chips it away
+*/
+#[derive(Component)]
+pub struct BunkerCell;
+
+impl BunkerCell {
+    pub const SIZE: Vec2 = Vec2::new(12.0, 12.0);
+    pub const COLOR: Color = Color::rgb(0.72, 0.25, 0.05);
+    pub const LAYER: f32 = 0.1;
+    // the bottom row of every bunker, just above the ships
+    const BOTTOM: f32 = BOTTOM_WALL + 150.0;
+}
+
+#[derive(Bundle)]
+struct BunkerCellBundle {
+    #[bundle]
+    sprite_bundle: SpriteBundle,
+    collider: Collider,
+    cell: BunkerCell,
+}
+
+impl BunkerCellBundle {
+    fn new(translation: Vec2) -> BunkerCellBundle {
+        BunkerCellBundle {
+            sprite_bundle: SpriteBundle {
+                transform: Transform {
+                    translation: translation.extend(BunkerCell::LAYER),
+                    ..default()
+                },
+                sprite: Sprite {
+                    color: BunkerCell::COLOR,
+                    custom_size: Some(BunkerCell::SIZE),
+                    ..default()
+                },
+                ..default()
+            },
+            collider: Collider { size: BunkerCell::SIZE },
+            cell: BunkerCell,
+        }
+    }
+}
+
+pub struct BunkersPlugin;
+
+impl Plugin for BunkersPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_simulation_system(SimulationPhase::Damage, check_for_bunker_collisions.run_in_state(GameState::Playing))
+            .add_enter_system(GameState::LoadWaveState, spawn_bunkers.run_unless_resource_exists::<Paused>());
+    }
+}
+
+fn spawn_bunkers(
+    mut commands: Commands,
+    global: Res<Global>,
+    waves: Res<Waves>,
+    wave_manifests: Res<Assets<WaveManifest>>,
+    wave_assets: Res<Assets<Wave>>,
+    cell_query: Query<Entity, With<BunkerCell>>,
+) {
+    let bunkers = match waves.current(&global, &wave_manifests, &wave_assets) {
+        Some(wave) => wave.bunkers.clone(),
+        None => return,
+    };
+
+    let keep = matches!(&bunkers, Some(bunkers) if bunkers.mode == BunkerMode::Persist) && !cell_query.is_empty();
+    if keep {
+        return;
+    }
+
+    for cell in cell_query.iter() {
+        commands.entity(cell).despawn();
+    }
+
+    if let Some(bunkers) = bunkers {
+        for translation in bunker_cells(&bunkers) {
+            commands.spawn().insert_bundle(BunkerCellBundle::new(translation));
+        }
+    }
+}
+
+/*
+  The centre of every cell, with the bunkers spaced evenly between the walls
+*/
+fn bunker_cells(bunkers: &Bunkers) -> Vec<Vec2> {
+    let rows = bunkers.shape.len();
+    let columns = bunkers.columns();
+    let spacing = bunkers.spacing();
+
+    let mut cells = Vec::new();
+    for bunker in 0..bunkers.count {
+        let center_x = LEFT_WALL + spacing * (bunker as f32 + 0.5);
+        let left = center_x - columns as f32 * BunkerCell::SIZE.x / 2.0;
+
+        for (row, cells_in_row) in bunkers.shape.iter().enumerate() {
+            // the first row of the shape is the top of the bunker
+            let y = BunkerCell::BOTTOM + (rows - 1 - row) as f32 * BunkerCell::SIZE.y;
+
+            for (col, cell) in cells_in_row.chars().enumerate() {
+                if cell != ' ' {
+                    cells.push(Vec2::new(left + (col as f32 + 0.5) * BunkerCell::SIZE.x, y));
+                }
+            }
+        }
+    }
+
+    cells
+}
+
+fn check_for_bunker_collisions(mut commands: Commands, mut hits: EventReader<BulletHitBunker>) {
+    // a cell can stop more than one bullet in the same step
+    let mut destroyed = HashSet::new();
+
+    for hit in hits.iter() {
+        commands.entity(hit.bullet).despawn_recursive();
+
+        if destroyed.insert(hit.cell) {
+            commands.entity(hit.cell).despawn();
+        }
+    }
+}
@@ -0,0 +1,518 @@
+use bevy::{input::InputSystem, prelude::*, utils::HashSet};
+use iyes_loopless::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::{storage, GameState};
+
+const CONTROLS_KEY: &str = "controls.ron";
+// how far a stick has to lean before it counts as pressed
+const AXIS_THRESHOLD: f32 = 0.5;
+const STICK_AXES: [GamepadAxisType; 4] = [
+    GamepadAxisType::LeftStickX,
+    GamepadAxisType::LeftStickY,
+    GamepadAxisType::RightStickX,
+    GamepadAxisType::RightStickY,
+];
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    MoveLeft,
+    MoveRight,
+    FireLeft,
+    FireRight,
+    Confirm,
+    Back,
+    Pause,
+    Up,
+    Down,
+    SecondMoveLeft,
+    SecondMoveRight,
+    SecondFireLeft,
+    SecondFireRight,
+}
+
+impl Action {
+    const ALL: [Action; 13] = [
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::FireLeft,
+        Action::FireRight,
+        Action::Confirm,
+        Action::Back,
+        Action::Pause,
+        Action::Up,
+        Action::Down,
+        Action::SecondMoveLeft,
+        Action::SecondMoveRight,
+        Action::SecondFireLeft,
+        Action::SecondFireRight,
+    ];
+
+    /*
+      The move left, move right, fire left and fire right actions of each player's ship
+    */
+    pub const SHIPS: [[Action; 4]; 2] = [
+        [Action::MoveLeft, Action::MoveRight, Action::FireLeft, Action::FireRight],
+        [Action::SecondMoveLeft, Action::SecondMoveRight, Action::SecondFireLeft, Action::SecondFireRight],
+    ];
+
+    /*
+      The player whose ship the action steers, menu actions belong to everyone
+    */
+    fn player(&self) -> Option<usize> {
+        Action::SHIPS.iter().position(|actions| actions.contains(self))
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::MoveLeft => "Move Left",
+            Action::MoveRight => "Move Right",
+            Action::FireLeft => "Fire Left",
+            Action::FireRight => "Fire Right",
+            Action::Confirm => "Confirm",
+            Action::Back => "Back",
+            Action::Pause => "Pause",
+            Action::Up => "Menu Up",
+            Action::Down => "Menu Down",
+            Action::SecondMoveLeft => "P2 Move Left",
+            Action::SecondMoveRight => "P2 Move Right",
+            Action::SecondFireLeft => "P2 Fire Left",
+            Action::SecondFireRight => "P2 Fire Right",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AxisDirection {
+    Positive,
+    Negative,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Binding {
+    Key(KeyCode),
+    Button(GamepadButtonType),
+    Axis(GamepadAxisType, AxisDirection),
+}
+
+impl Binding {
+    fn is_gamepad(&self) -> bool {
+        !matches!(self, Binding::Key(_))
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Binding::Key(key) => format!("{:?}", key),
+            Binding::Button(button) => format!("Pad {:?}", button),
+            Binding::Axis(axis, AxisDirection::Positive) => format!("Pad {:?}+", axis),
+            Binding::Axis(axis, AxisDirection::Negative) => format!("Pad {:?}-", axis),
+        }
+    }
+
+    /*
+      Gamepad bindings are only read from the given gamepads
+    */
+    fn is_pressed(&self, devices: &Devices, gamepads: &[Gamepad]) -> bool {
+        match *self {
+            Binding::Key(key) => devices.keyboard.pressed(key),
+            Binding::Button(button) => gamepads
+                .iter()
+                .any(|gamepad| devices.buttons.pressed(GamepadButton::new(*gamepad, button))),
+            Binding::Axis(axis, direction) => gamepads.iter().any(|gamepad| {
+                let value = devices.axes.get(GamepadAxis::new(*gamepad, axis)).unwrap_or(0.0);
+                match direction {
+                    AxisDirection::Positive => value > AXIS_THRESHOLD,
+                    AxisDirection::Negative => value < -AXIS_THRESHOLD,
+                }
+            }),
+        }
+    }
+}
+
+/*
+  The input devices an action can come from
+*/
+struct Devices<'a> {
+    keyboard: &'a Input<KeyCode>,
+    buttons: &'a Input<GamepadButton>,
+    axes: &'a Axis<GamepadAxis>,
+    gamepads: &'a Gamepads,
+}
+
+impl Devices<'_> {
+    /*
+      Whatever was pressed this frame, for rebinding. A stick only counts in the frame it crosses the threshold,
+      so one already leaning (in leaning_before) doesn't get bound.
+    */
+    fn pressed_binding(&self, leaning_before: &[Binding]) -> Option<Binding> {
+        if let Some(key) = self.keyboard.get_just_pressed().next() {
+            return Some(Binding::Key(*key));
+        }
+        if let Some(button) = self.buttons.get_just_pressed().next() {
+            return Some(Binding::Button(button.button_type));
+        }
+
+        self.leaning_axes().into_iter().find(|binding| !leaning_before.contains(binding))
+    }
+
+    /*
+      The stick directions leaning past the threshold on any gamepad
+    */
+    fn leaning_axes(&self) -> Vec<Binding> {
+        let mut leaning = Vec::new();
+        for gamepad in self.gamepads.iter() {
+            for axis in STICK_AXES {
+                let value = self.axes.get(GamepadAxis::new(*gamepad, axis)).unwrap_or(0.0);
+                if value > AXIS_THRESHOLD {
+                    leaning.push(Binding::Axis(axis, AxisDirection::Positive));
+                } else if value < -AXIS_THRESHOLD {
+                    leaning.push(Binding::Axis(axis, AxisDirection::Negative));
+                }
+            }
+        }
+        leaning
+    }
+
+    /*
+      A gamepad button newly pressed on any gamepad, for finding your way around screens
+    */
+    fn button_just_pressed(&self, button: GamepadButtonType) -> bool {
+        self.gamepads
+            .iter()
+            .any(|gamepad| self.buttons.just_pressed(GamepadButton::new(*gamepad, button)))
+    }
+}
+
+/*
+  Which keys, buttons and sticks trigger each action, saved across runs
+*/
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Controls {
+    bindings: HashMap<Action, Vec<Binding>>,
+}
+
+impl Default for Controls {
+    fn default() -> Controls {
+        let key = Binding::Key;
+        let button = Binding::Button;
+        let stick = Binding::Axis;
+
+        let bindings = [
+            (Action::MoveLeft, vec![
+                key(KeyCode::A),
+                button(GamepadButtonType::DPadLeft),
+                stick(GamepadAxisType::LeftStickX, AxisDirection::Negative),
+            ]),
+            (Action::MoveRight, vec![
+                key(KeyCode::D),
+                button(GamepadButtonType::DPadRight),
+                stick(GamepadAxisType::LeftStickX, AxisDirection::Positive),
+            ]),
+            (Action::FireLeft, vec![key(KeyCode::J), button(GamepadButtonType::LeftTrigger2), button(GamepadButtonType::West)]),
+            (Action::FireRight, vec![key(KeyCode::K), button(GamepadButtonType::RightTrigger2), button(GamepadButtonType::North)]),
+            (Action::Confirm, vec![key(KeyCode::Space), key(KeyCode::Return), button(GamepadButtonType::South)]),
+            (Action::Back, vec![key(KeyCode::M), button(GamepadButtonType::East)]),
+            (Action::Pause, vec![key(KeyCode::P), key(KeyCode::Escape), button(GamepadButtonType::Start)]),
+            // moves the cursor in the menus and picks the letters of your initials
+            (Action::Up, vec![
+                key(KeyCode::Up),
+                key(KeyCode::W),
+                button(GamepadButtonType::DPadUp),
+                stick(GamepadAxisType::LeftStickY, AxisDirection::Positive),
+            ]),
+            (Action::Down, vec![
+                key(KeyCode::Down),
+                key(KeyCode::S),
+                button(GamepadButtonType::DPadDown),
+                stick(GamepadAxisType::LeftStickY, AxisDirection::Negative),
+            ]),
+            // the second player sits at the arrow keys, with the same layout on the second gamepad
+            (Action::SecondMoveLeft, vec![
+                key(KeyCode::Left),
+                button(GamepadButtonType::DPadLeft),
+                stick(GamepadAxisType::LeftStickX, AxisDirection::Negative),
+            ]),
+            (Action::SecondMoveRight, vec![
+                key(KeyCode::Right),
+                button(GamepadButtonType::DPadRight),
+                stick(GamepadAxisType::LeftStickX, AxisDirection::Positive),
+            ]),
+            (Action::SecondFireLeft, vec![key(KeyCode::Comma), button(GamepadButtonType::LeftTrigger2), button(GamepadButtonType::West)]),
+            (Action::SecondFireRight, vec![key(KeyCode::Period), button(GamepadButtonType::RightTrigger2), button(GamepadButtonType::North)]),
+        ];
+
+        Controls {
+            bindings: bindings.into_iter().collect(),
+        }
+    }
+}
+
+impl Controls {
+    pub fn load() -> Controls {
+        let mut controls: Controls = storage::load(CONTROLS_KEY)
+            .and_then(|saved| ron::de::from_str(&saved).ok())
+            .unwrap_or_default();
+
+        // actions added since the controls were saved start out with their defaults
+        for (action, bindings) in Controls::default().bindings {
+            controls.bindings.entry(action).or_insert(bindings);
+        }
+        controls
+    }
+
+    pub fn save(&self) {
+        let result = ron::ser::to_string(self)
+            .map_err(|error| error.to_string())
+            .and_then(|serialized| storage::save(CONTROLS_KEY, &serialized));
+
+        if let Err(error) = result {
+            warn!("Failed to save controls: {}", error);
+        }
+    }
+
+    fn bindings(&self, action: Action) -> &[Binding] {
+        self.bindings.get(&action).map_or(&[], |bindings| bindings.as_slice())
+    }
+
+    /*
+      The keyboard keys of an action for on screen hints, like "Space/Return"
+    */
+    pub fn describe(&self, action: Action) -> String {
+        let keys: Vec<String> = self
+            .bindings(action)
+            .iter()
+            .filter(|binding| !binding.is_gamepad())
+            .map(|binding| binding.name())
+            .collect();
+
+        if keys.is_empty() {
+            "-".to_string()
+        } else {
+            keys.join("/")
+        }
+    }
+
+    /*
+      Replaces the first binding of the same kind (keyboard or gamepad), or adds it if there is none
+    */
+    fn rebind(&mut self, action: Action, binding: Binding) {
+        let bindings = self.bindings.entry(action).or_default();
+        if bindings.contains(&binding) {
+            return;
+        }
+
+        match bindings.iter().position(|existing| existing.is_gamepad() == binding.is_gamepad()) {
+            Some(index) => bindings[index] = binding,
+            None => bindings.push(binding),
+        }
+    }
+}
+
+/*
+  The actions held and newly pressed this frame, read this instead of the devices
+*/
+#[derive(Default)]
+pub struct ActionState {
+    pressed: HashSet<Action>,
+    just_pressed: HashSet<Action>,
+}
+
+impl ActionState {
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.just_pressed.contains(&action)
+    }
+}
+
+#[derive(SystemLabel, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ActionSystem;
+
+/*
+  Present while the controls screen is open over the menu
+*/
+pub struct ControlsScreen {
+    selected: usize,
+    rebinding: bool,
+    // the sticks leaning last frame, see Devices::pressed_binding
+    leaning: Vec<Binding>,
+}
+
+#[derive(Component)]
+struct ControlsScreenText;
+
+pub struct ControlsPlugin;
+
+impl Plugin for ControlsPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.world.contains_resource::<Controls>() {
+            app.insert_resource(Controls::load());
+        }
+
+        app.init_resource::<ActionState>()
+            .add_system_to_stage(CoreStage::PreUpdate, update_actions.label(ActionSystem).after(InputSystem))
+            .add_system(update_controls_screen.run_if_resource_exists::<ControlsScreen>())
+            .add_exit_system(GameState::Menu, close_controls_screen);
+    }
+}
+
+fn update_actions(
+    keyboard: Res<Input<KeyCode>>,
+    buttons: Res<Input<GamepadButton>>,
+    axes: Res<Axis<GamepadAxis>>,
+    gamepads: Res<Gamepads>,
+    controls: Res<Controls>,
+    mut actions: ResMut<ActionState>,
+) {
+    let devices = Devices {
+        keyboard: &keyboard,
+        buttons: &buttons,
+        axes: &axes,
+        gamepads: &gamepads,
+    };
+
+    // each ship listens to its own gamepad, in the order they are listed
+    let all_gamepads: Vec<Gamepad> = gamepads.iter().copied().collect();
+    let pressed: HashSet<Action> = Action::ALL
+        .into_iter()
+        .filter(|action| {
+            let gamepads = match action.player() {
+                Some(player) => all_gamepads.get(player..=player).unwrap_or(&[]),
+                None => &all_gamepads,
+            };
+            controls.bindings(*action).iter().any(|binding| binding.is_pressed(&devices, gamepads))
+        })
+        .collect();
+
+    actions.just_pressed = pressed.difference(&actions.pressed).copied().collect();
+    actions.pressed = pressed;
+}
+
+fn screen_text(screen: &ControlsScreen, controls: &Controls) -> String {
+    let mut text = String::from("Controls\n\n");
+
+    for (index, action) in Action::ALL.iter().enumerate() {
+        let marker = if index == screen.selected { ">" } else { " " };
+        let bindings = if screen.rebinding && index == screen.selected {
+            "press a key or button...".to_string()
+        } else {
+            let names: Vec<String> = controls.bindings(*action).iter().map(|binding| binding.name()).collect();
+            names.join(", ")
+        };
+        text.push_str(&format!("{} {:<13} {}\n", marker, action.name(), bindings));
+    }
+
+    text.push_str(&format!(
+        "\n[{}/{}] Select  [Enter] Rebind  [Backspace] Defaults  [Esc] Back",
+        controls.describe(Action::Up),
+        controls.describe(Action::Down),
+    ));
+    text.push_str("\n[D-Pad] Select  [Pad South] Rebind  [Pad Select] Defaults  [Pad East] Back");
+    text
+}
+
+/*
+  Opened from the menu, the menu ignores its own keys while it exists
+*/
+pub fn open_controls_screen(commands: &mut Commands, asset_server: &AssetServer, controls: &Controls) {
+    let screen = ControlsScreen {
+        selected: 0,
+        rebinding: false,
+        leaning: Vec::new(),
+    };
+
+    commands
+        .spawn()
+        .insert(ControlsScreenText)
+        .insert(Name::new("Controls Screen"))
+        .insert_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            color: Color::rgba(0.0, 0.0, 0.0, 0.9).into(),
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle::from_section(
+                screen_text(&screen, controls),
+                TextStyle {
+                    font_size: 28.0,
+                    color: Color::rgb(1.0, 1.0, 1.0),
+                    font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+                },
+            ));
+        });
+
+    commands.insert_resource(screen);
+}
+
+fn update_controls_screen(
+    mut commands: Commands,
+    keyboard: Res<Input<KeyCode>>,
+    buttons: Res<Input<GamepadButton>>,
+    axes: Res<Axis<GamepadAxis>>,
+    gamepads: Res<Gamepads>,
+    actions: Res<ActionState>,
+    mut controls: ResMut<Controls>,
+    mut screen: ResMut<ControlsScreen>,
+    screen_query: Query<(Entity, &Children), With<ControlsScreenText>>,
+    mut text_query: Query<&mut Text>,
+) {
+    let devices = Devices {
+        keyboard: &keyboard,
+        buttons: &buttons,
+        axes: &axes,
+        gamepads: &gamepads,
+    };
+    let action = Action::ALL[screen.selected];
+    let defaults = keyboard.just_pressed(KeyCode::Back) || devices.button_just_pressed(GamepadButtonType::Select);
+    let leaning_before = std::mem::replace(&mut screen.leaning, devices.leaning_axes());
+
+    if screen.rebinding {
+        // escape cancels, so it can't be bound from here
+        if keyboard.just_pressed(KeyCode::Escape) {
+            screen.rebinding = false;
+        } else if let Some(binding) = devices.pressed_binding(&leaning_before) {
+            controls.rebind(action, binding);
+            controls.save();
+            screen.rebinding = false;
+        }
+    } else if keyboard.just_pressed(KeyCode::Escape) || actions.just_pressed(Action::Back) {
+        for (entity, _) in screen_query.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+        commands.remove_resource::<ControlsScreen>();
+        return;
+    } else if actions.just_pressed(Action::Up) {
+        screen.selected = (screen.selected + Action::ALL.len() - 1) % Action::ALL.len();
+    } else if actions.just_pressed(Action::Down) {
+        screen.selected = (screen.selected + 1) % Action::ALL.len();
+    } else if defaults {
+        *controls = Controls::default();
+        controls.save();
+    } else if actions.just_pressed(Action::Confirm) {
+        screen.rebinding = true;
+    }
+
+    for (_, children) in screen_query.iter() {
+        for child in children.iter() {
+            if let Ok(mut text) = text_query.get_mut(*child) {
+                text.sections[0].value = screen_text(&screen, &controls);
+            }
+        }
+    }
+}
+
+fn close_controls_screen(mut commands: Commands, screen_query: Query<Entity, With<ControlsScreenText>>) {
+    for entity in screen_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    commands.remove_resource::<ControlsScreen>();
+}
@@ -1,8 +1,11 @@
+// everything below is only registered with the debug feature
+#![cfg_attr(not(feature = "debug"), allow(dead_code, unused_imports))]
+
 use bevy::prelude::*;
 use bevy_inspector_egui::WorldInspectorPlugin;
 use iyes_loopless::state::NextState;
 
-use crate::{shared::{Collider, Health}, aliens::Alien, GameState, Global};
+use crate::{shared::{Bullet, Collider}, aliens::Alien, GameState, Global};
 
 pub struct DebugPlugin;
 
@@ -11,11 +14,53 @@ impl Plugin for DebugPlugin {
     fn build(&self, app: &mut App) {
         println!("Debugging enabled");
         app.add_plugin(WorldInspectorPlugin::new())
+            .add_startup_system(spawn_bullet_counter)
             .add_system(draw_bounding_boxes)
+            .add_system(update_bullet_counter)
             .add_system(goto_next_wave);
     }
     #[cfg(not(feature = "debug"))]
-    fn build(&self, app: &mut App) {}
+    fn build(&self, _app: &mut App) {}
+}
+
+#[derive(Component)]
+struct BulletCounter;
+
+fn spawn_bullet_counter(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn()
+        .insert(BulletCounter)
+        .insert(Name::new("Bullet Counter"))
+        .insert_bundle(
+            TextBundle::from_section(
+                "Bullets: 0",
+                TextStyle {
+                    font_size: 20.0,
+                    color: Color::rgb(1.0, 0.0, 0.0),
+                    font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    bottom: Val::Px(10.0),
+                    right: Val::Px(10.0),
+                    ..default()
+                },
+                ..default()
+            }),
+        );
+}
+
+fn update_bullet_counter(
+    bullet_query: Query<With<Bullet>>,
+    mut counter_query: Query<&mut Text, With<BulletCounter>>,
+) {
+    let live_bullets = bullet_query.iter().count();
+
+    for mut counter in counter_query.iter_mut() {
+        counter.sections[0].value = format!("Bullets: {}", live_bullets);
+    }
 }
 
 fn draw_bounding_boxes(mut commands: Commands, query: Query<(Entity, &Collider), Added<Collider>>) {
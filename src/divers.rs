@@ -0,0 +1,123 @@
+use bevy::prelude::*;
+use iyes_loopless::prelude::*;
+use rand::Rng;
+use std::time::Duration;
+
+use crate::{
+    paths::{Curve, PathFollower},
+    patterns::nearest_ship,
+    player::Ship,
+    rng::GameRng,
+    shared::*,
+    simulation::{SimulationApp, SimulationPhase},
+    GameState,
+};
+
+/*
+  An alien with AlienMovement::Dive. It holds its slot, then every so often swings out on a curve through
+  the nearest ship, leaves through the bottom of the screen and drops back into its slot from the top.
+*/
+#[derive(Component)]
+pub struct Diver {
+    slot: Vec2,
+    speed: f32,
+    pause: f32,
+    // until the next dive, counted from when it is back in its slot
+    timer: Timer,
+    leg: Option<DiveLeg>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum DiveLeg {
+    Out,
+    Back,
+}
+
+/*
+  Present while a Diver is away from its slot, it can't invade while it is
+*/
+#[derive(Component)]
+pub struct Diving;
+
+impl Diver {
+    // how far to the side of its slot a Diver swings out
+    const SWING: f32 = 250.0;
+
+    pub fn new(slot: Vec2, speed: f32, pause: f32, rng: &mut impl Rng) -> Diver {
+        Diver {
+            slot,
+            speed,
+            pause,
+            timer: Timer::from_seconds(rng.gen::<f32>() * pause, false),
+            leg: None,
+        }
+    }
+}
+
+pub struct DiversPlugin;
+
+impl Plugin for DiversPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_simulation_system(SimulationPhase::Movement, update_divers.run_in_state(GameState::Playing));
+    }
+}
+
+fn update_divers(
+    mut commands: Commands,
+    mut diver_query: Query<(Entity, &mut Diver, &mut Transform, &Collider, Option<&PathFollower>), Without<Ship>>,
+    ship_query: Query<(&Transform, &Velocity, &Health), With<Ship>>,
+    mut rng: ResMut<GameRng>,
+) {
+    for (diver_entity, mut diver, mut transform, collider, follower) in diver_query.iter_mut() {
+        // still flying in, or on its way out or back
+        if follower.is_some() {
+            continue;
+        }
+
+        let position = transform.translation.truncate();
+
+        match diver.leg {
+            None => {
+                diver.timer.tick(Duration::from_secs_f32(TIME_STEP));
+                if !diver.timer.finished() {
+                    continue;
+                }
+
+                // swings out to whichever side has more room, then comes down through the ship
+                let target = nearest_ship(ship_query.iter(), position).map_or(position.x, |ship| ship.position.x);
+                let side = if position.x > 0.0 { -1.0 } else { 1.0 };
+                let curve = Curve::Bezier(vec![
+                    position,
+                    Vec2::new(position.x + side * Diver::SWING, BOTTOM_WALL + WINDOW_HEIGHT / 3.0),
+                    Vec2::new(target, BOTTOM_WALL - collider.size.y),
+                ]);
+
+                diver.leg = Some(DiveLeg::Out);
+                commands
+                    .entity(diver_entity)
+                    .insert(Diving)
+                    .insert(PathFollower::at_speed(curve, 0.0, diver.speed));
+            }
+            Some(DiveLeg::Out) => {
+                // back in from the top, curving the other way
+                let start = Vec2::new(diver.slot.x, TOP_WALL + collider.size.y);
+                let side = if position.x > diver.slot.x { 1.0 } else { -1.0 };
+                let curve = Curve::Bezier(vec![
+                    start,
+                    Vec2::new(diver.slot.x + side * Diver::SWING / 2.0, (start.y + diver.slot.y) / 2.0),
+                    diver.slot,
+                ]);
+
+                transform.translation = start.extend(transform.translation.z);
+                diver.leg = Some(DiveLeg::Back);
+                commands.entity(diver_entity).insert(PathFollower::at_speed(curve, 0.0, diver.speed));
+            }
+            Some(DiveLeg::Back) => {
+                let pause = diver.pause;
+                diver.timer = Timer::from_seconds(rng.gameplay().gen::<f32>() * pause, false);
+                diver.leg = None;
+                commands.entity(diver_entity).remove::<Diving>();
+            }
+        }
+    }
+}
@@ -1,7 +1,14 @@
 use bevy::prelude::*;
 use iyes_loopless::prelude::*;
 
-use crate::{aliens::Alien, shared::reset_game, GameState};
+use crate::{
+    aliens::Alien,
+    controls::{Action, ActionState, Controls},
+    high_scores::InitialsEntry,
+    rng::GameRng,
+    shared::reset_game,
+    GameOverReason, GameState, Global,
+};
 
 #[derive(Component)]
 pub struct GameOverMenu;
@@ -12,7 +19,11 @@ impl Plugin for GameOverPlugin {
     fn build(&self, app: &mut App) {
         app.add_enter_system(GameState::GameOver, create_gameover_screen)
             .add_exit_system(GameState::GameOver, reset_game)
-            .add_system(update_gameover_menu.run_in_state(GameState::GameOver));
+            .add_system(
+                update_gameover_menu
+                    .run_in_state(GameState::GameOver)
+                    .run_unless_resource_exists::<InitialsEntry>(),
+            );
     }
 }
 
@@ -20,18 +31,25 @@ fn create_gameover_screen(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
     alien_query: Query<Entity, With<Alien>>, // aliens should be cleared by the AliensPlugin
+    reason: Res<GameOverReason>,
+    rng: Res<GameRng>,
+    controls: Res<Controls>,
+    global: Res<Global>,
 ) {
+    let reason_text = match *reason {
+        GameOverReason::ShipDestroyed if global.players() > 1 => "Every Ferris was destroyed\n\n",
+        GameOverReason::ShipDestroyed => "Ferris was destroyed\n\n",
+        GameOverReason::Invaded => "The aliens reached the ground\n\n",
+    };
+
     for alien_entity in alien_query.iter() {
         commands.entity(alien_entity).despawn();
     }
 
-    commands
-        .spawn()
-        .insert(GameOverMenu)
-        .insert_bundle(
+    commands.spawn().insert(GameOverMenu).insert_bundle(
         TextBundle::from_sections([
             TextSection::new(
-                "GAME OVER\n\n",
+                "GAME OVER\n",
                 TextStyle {
                     font_size: 40.0,
                     color: Color::rgb(0.8, 0.0, 0.0),
@@ -39,7 +57,27 @@ fn create_gameover_screen(
                 },
             ),
             TextSection::new(
-                "[R] Retry\n[M] Menu\n[ESC] Quit",
+                reason_text,
+                TextStyle {
+                    font_size: 30.0,
+                    color: Color::rgb(0.7, 0.7, 0.7),
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                },
+            ),
+            TextSection::new(
+                format!("Seed: {}\n\n", rng.seed()),
+                TextStyle {
+                    font_size: 25.0,
+                    color: Color::rgb(0.7, 0.7, 0.7),
+                    font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+                },
+            ),
+            TextSection::new(
+                format!(
+                    "[{}] Retry\n[{}] Menu\n[ESC] Quit",
+                    controls.describe(Action::Confirm),
+                    controls.describe(Action::Back),
+                ),
                 TextStyle {
                     font_size: 30.0,
                     color: Color::rgb(1.0, 1.0, 1.0),
@@ -61,9 +99,10 @@ fn create_gameover_screen(
     );
 }
 
-fn update_gameover_menu(mut commands: Commands, keyboard_input: Res<Input<KeyCode>>) {
-    let restart = keyboard_input.pressed(KeyCode::R);
-    let menu = keyboard_input.pressed(KeyCode::M);
+fn update_gameover_menu(mut commands: Commands, actions: Res<ActionState>) {
+    // a fire button still held from the run shouldn't skip the screen
+    let restart = actions.just_pressed(Action::Confirm);
+    let menu = actions.just_pressed(Action::Back);
 
     if menu {
         commands.insert_resource(NextState(GameState::Menu));
@@ -71,5 +110,3 @@ fn update_gameover_menu(mut commands: Commands, keyboard_input: Res<Input<KeyCod
         commands.insert_resource(NextState(GameState::LoadWaveState));
     }
 }
-
-
@@ -1,10 +1,8 @@
-use std::time::Duration;
-
 use bevy::prelude::*;
 use iyes_loopless::prelude::*;
-use rand::random;
+use rand::Rng;
 
-use crate::{shared::{reset_game, WINDOW_WIDTH, BOTTOM_WALL, LEFT_WALL, Velocity, DespawnTimer, WINDOW_HEIGHT, TIME_STEP, Sprites}, GameState, Scoreboard};
+use crate::{controls::{Action, ActionState, Controls}, high_scores::InitialsEntry, rng::GameRng, shared::{reset_game, WINDOW_WIDTH, BOTTOM_WALL, LEFT_WALL, Velocity, DespawnTimer, WINDOW_HEIGHT, TIME_STEP, Sprites}, simulation::{SimulationApp, SimulationPhase}, GameState, Scoreboard};
 
 #[derive(Component)]
 pub struct HallOfFame;
@@ -13,24 +11,11 @@ pub struct HallOfFamePlugin;
 
 impl Plugin for HallOfFamePlugin {
     fn build(&self, app: &mut App) {
-        let mut fixedupdate = SystemStage::parallel();
-        fixedupdate.add_system_set(
-            ConditionSet::new()
-                .label("Hall Of Fame Updates")
-                .run_in_state(GameState::Victory)
-                .with_system(HallOfFame::update)
-                .into(),
-        );
-
         app.add_enter_system(GameState::Victory, reset_game.before(HallOfFame::create))
             .add_startup_system(HallOfFame::load_assets)
             .add_enter_system(GameState::Victory, HallOfFame::create)
             .add_exit_system(GameState::Victory, HallOfFame::cleanup)
-            .add_stage_before(
-                CoreStage::Update,
-                "Hall Of Fame Fixed Timestep",
-                FixedTimestepStage::from_stage(Duration::from_secs_f32(TIME_STEP), fixedupdate),
-            );
+            .add_simulation_system(SimulationPhase::Movement, HallOfFame::update.run_in_state(GameState::Victory));
     }
 }
 
@@ -47,7 +32,8 @@ impl HallOfFame {
         mut commands: Commands,
         asset_server: Res<AssetServer>,
         scoreboard: Res<Scoreboard>,
-        sprites: Res<Sprites>
+        sprites: Res<Sprites>,
+        controls: Res<Controls>,
     ) {
         commands
             .spawn()
@@ -72,7 +58,11 @@ impl HallOfFame {
                     },
                 ),
                 TextSection::new(
-                    "[Space] Bask in Glory\n[M] Menu\n[ESC] Quit",
+                    format!(
+                        "[{}] Bask in Glory\n[{}] Menu\n[ESC] Quit",
+                        controls.describe(Action::Confirm),
+                        controls.describe(Action::Back),
+                    ),
                     TextStyle {
                         font_size: 30.0,
                         color: Color::rgb(1.0, 1.0, 1.0),
@@ -114,26 +104,32 @@ impl HallOfFame {
 
     fn update(
         mut commands: Commands, 
-        keyboard_input: Res<Input<KeyCode>>, 
+        actions: Res<ActionState>,
         mut bullet_query: Query<(&mut Transform, &Velocity), With<HallOfFame>>,
-        sprites: Res<Sprites>
+        sprites: Res<Sprites>,
+        initials_entry: Option<Res<InitialsEntry>>,
+        mut rng: ResMut<GameRng>,
     ) {
-        let bask_in_glory = keyboard_input.pressed(KeyCode::Space);
-        let menu = keyboard_input.pressed(KeyCode::M);
+        let rng = rng.cosmetic();
+
+        // keys type initials until they are saved
+        let accepts_input = initials_entry.is_none();
+        let bask_in_glory = accepts_input && actions.pressed(Action::Confirm);
+        let menu = accepts_input && actions.just_pressed(Action::Back);
 
         if menu {
             commands.insert_resource(NextState(GameState::Menu));
         } else if bask_in_glory {
-            for _ in 0..(20.0 * random::<f32>()) as i32 {
-                 let bullet_x = LEFT_WALL + WINDOW_WIDTH * random::<f32>(); 
+            for _ in 0..(20.0 * rng.gen::<f32>()) as i32 {
+                 let bullet_x = LEFT_WALL + WINDOW_WIDTH * rng.gen::<f32>(); 
                  let bullet_y = BOTTOM_WALL - 100.0; 
 
                  let velocity = Vec2::new(
                     0.0,
-                    f32::max(250.0, WINDOW_HEIGHT * (0.5 + random::<f32>())), 
+                    f32::max(250.0, WINDOW_HEIGHT * (0.5 + rng.gen::<f32>())), 
                 );
 
-                let bullet_path = format!("HOF_BULLET_{}", (random::<f32>() * 9.0) as usize);
+                let bullet_path = format!("HOF_BULLET_{}", (rng.gen::<f32>() * 9.0) as usize);
 
                 commands
                     .spawn()
@@ -143,7 +139,6 @@ impl HallOfFame {
                             translation: Vec3::new(bullet_x, bullet_y, 0.0), 
                             scale: Vec3::new(2.0, 1.0, 1.0),
                             rotation: Quat::from_rotation_z(180.0f32.to_radians()),
-                            ..default()
                         },
                         texture: sprites.get(bullet_path.as_str()),
                         sprite: Sprite {
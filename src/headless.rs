@@ -0,0 +1,288 @@
+use bevy::{
+    asset::AssetPlugin,
+    audio::AudioSink,
+    ecs::query::ReadOnlyWorldQuery,
+    input::{keyboard::KeyboardInput, ButtonState, InputPlugin},
+    prelude::*,
+    window::WindowPlugin,
+};
+use iyes_loopless::prelude::*;
+use std::{thread, time::Duration};
+
+use crate::{
+    add_game,
+    archetypes::{Archetypes, Roster},
+    controls::Controls,
+    replay::SimulationClock,
+    rng::GameRng,
+    waves::{WaveManifest, Waves},
+    weapons::{Arsenal, Weapons},
+    GameMode, GameState, Global,
+};
+
+// waves, weapons and the alien roster load from disk on the io task pool
+const MAX_LOADING_FRAMES: u32 = 10_000;
+const LOADING_FRAME_SLEEP: Duration = Duration::from_millis(1);
+
+/*
+  The game without a window, renderer or audio output, stepping the fixed timestep once per update.
+  Images, fonts and sounds have no loaders here, so their handles stay unloaded stubs:
+  Sprites and Animations still get every name registered, which is all the game systems look at.
+*/
+pub fn app() -> App {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .add_plugin(TransformPlugin)
+        .add_plugin(HierarchyPlugin)
+        .add_plugin(InputPlugin)
+        .add_plugin(WindowPlugin)
+        .add_plugin(AssetPlugin)
+        // what the sprite, ui and audio plugins would otherwise register
+        .add_asset::<Image>()
+        .add_asset::<TextureAtlas>()
+        .add_asset::<ColorMaterial>()
+        .add_asset::<Font>()
+        .add_asset::<AudioSource>()
+        .add_asset::<AudioSink>()
+        .init_resource::<Audio>()
+        .insert_resource(SimulationClock::fixed(1))
+        // the player's saved bindings stay out of the tests
+        .insert_resource(Controls::default());
+
+    add_game(&mut app);
+    app.update();
+    app
+}
+
+pub fn state(app: &App) -> GameState {
+    app.world.resource::<CurrentState<GameState>>().0.clone()
+}
+
+/*
+  Runs n frames, which is n fixed timesteps
+*/
+pub fn step(app: &mut App, n: u32) {
+    for _ in 0..n {
+        app.update();
+    }
+}
+
+/*
+  Steps until the game reaches the state, returning the frames it took
+*/
+pub fn step_until(app: &mut App, state: GameState, max_frames: u32) -> u32 {
+    for frame in 0..max_frames {
+        if self::state(app) == state {
+            return frame;
+        }
+        app.update();
+    }
+    panic!("still in {:?} after {} frames, expected {:?}", self::state(app), max_frames, state);
+}
+
+pub fn count<F: ReadOnlyWorldQuery>(app: &mut App) -> usize {
+    app.world.query_filtered::<Entity, F>().iter(&app.world).count()
+}
+
+pub fn wait_for_assets(app: &mut App) {
+    for _ in 0..MAX_LOADING_FRAMES {
+        let world = &app.world;
+        let waves_loaded = world
+            .resource::<Waves>()
+            .is_loaded(world.resource::<AssetServer>(), world.resource::<Assets<WaveManifest>>());
+        let weapons_loaded = world.resource::<Weapons>().is_loaded(world.resource::<Assets<Arsenal>>());
+        let roster_loaded = world.resource::<Archetypes>().is_loaded(world.resource::<Assets<Roster>>());
+
+        if waves_loaded && weapons_loaded && roster_loaded {
+            return;
+        }
+        thread::sleep(LOADING_FRAME_SLEEP);
+        app.update();
+    }
+    panic!("waves, weapons and the alien roster didn't load");
+}
+
+/*
+  What the menu does when a run starts
+*/
+pub fn start_run(app: &mut App, seed: u64, mode: GameMode) {
+    wait_for_assets(app);
+
+    app.world.insert_resource(GameRng::new(seed));
+    app.world.resource_mut::<Global>().start_playing(mode);
+    app.world.insert_resource(NextState(GameState::LoadWaveState));
+}
+
+/*
+  Sends the key event a window would, so just_pressed works like it does in the game
+*/
+pub fn set_key(app: &mut App, key_code: KeyCode, pressed: bool) {
+    if app.world.resource::<Input<KeyCode>>().pressed(key_code) == pressed {
+        return;
+    }
+
+    app.world.send_event(KeyboardInput {
+        scan_code: 0,
+        key_code: Some(key_code),
+        state: if pressed { ButtonState::Pressed } else { ButtonState::Released },
+    });
+}
+
+/*
+  Presses the key for a single frame
+*/
+pub fn tap(app: &mut App, key_code: KeyCode) {
+    set_key(app, key_code, true);
+    app.update();
+    set_key(app, key_code, false);
+}
+
+mod tests {
+    use super::*;
+    use bevy::ecs::system::CommandQueue;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+    use crate::{
+        aliens::{spawn_alien, Alien, AlienTuning},
+        divers::Diving,
+        player::{HealthDisplayHeart, Player, Ship, INITIAL_HEALTH_POINTS},
+        shared::Health,
+        Scoreboard,
+    };
+
+    // the longest wave intro plus the pause before the aliens move
+    const MAX_LOADING_WAVE_FRAMES: u32 = 60 * 10;
+
+    fn kill_ship(app: &mut App) {
+        for mut health in app.world.query_filtered::<&mut Health, With<Ship>>().iter_mut(&mut app.world) {
+            health.0 = 0;
+        }
+    }
+
+    fn kill_player(app: &mut App, index: usize) {
+        for (player, mut health) in app.world.query_filtered::<(&Player, &mut Health), With<Ship>>().iter_mut(&mut app.world) {
+            if player.0 == index {
+                health.0 = 0;
+            }
+        }
+    }
+
+    fn clear_wave(app: &mut App) {
+        let aliens: Vec<Entity> = app.world.query_filtered::<Entity, With<Alien>>().iter(&app.world).collect();
+        for alien in aliens {
+            app.world.entity_mut(alien).despawn_recursive();
+        }
+    }
+
+    #[test]
+    fn starts_in_the_menu() {
+        let mut app = app();
+        wait_for_assets(&mut app);
+
+        assert_eq!(state(&app), GameState::Menu);
+        assert_eq!(count::<With<Ship>>(&mut app), 0);
+        assert_eq!(count::<With<Alien>>(&mut app), 0);
+    }
+
+    #[test]
+    fn game_over_and_retry() {
+        let mut app = app();
+        wait_for_assets(&mut app);
+
+        tap(&mut app, KeyCode::Space);
+        step_until(&mut app, GameState::LoadWaveState, 10);
+        assert_eq!(app.world.resource::<Global>().current_wave(), 0);
+        assert_eq!(count::<With<Ship>>(&mut app), 1);
+        assert!(count::<With<Alien>>(&mut app) > 0);
+
+        step_until(&mut app, GameState::Playing, MAX_LOADING_WAVE_FRAMES);
+        step(&mut app, 10);
+
+        kill_ship(&mut app);
+        step_until(&mut app, GameState::GameOver, 10);
+        assert_eq!(count::<With<Alien>>(&mut app), 0);
+
+        tap(&mut app, KeyCode::Space);
+        step_until(&mut app, GameState::LoadWaveState, 10);
+        assert_eq!(app.world.resource::<Global>().current_wave(), 0);
+        assert_eq!(app.world.resource::<Scoreboard>().score, 0);
+        assert_eq!(count::<With<Ship>>(&mut app), 1);
+
+        step_until(&mut app, GameState::Playing, MAX_LOADING_WAVE_FRAMES);
+    }
+
+    #[test]
+    fn co_op_ends_when_every_ship_is_down() {
+        let mut app = app();
+        app.world.resource_mut::<Global>().set_players(2);
+        start_run(&mut app, 7, GameMode::Campaign);
+
+        step_until(&mut app, GameState::Playing, MAX_LOADING_WAVE_FRAMES);
+        step(&mut app, 2);
+        assert_eq!(count::<With<Ship>>(&mut app), 2);
+        assert_eq!(count::<With<HealthDisplayHeart>>(&mut app), 2 * INITIAL_HEALTH_POINTS as usize);
+        assert_eq!(app.world.resource::<Scoreboard>().players.len(), 2);
+
+        kill_player(&mut app, 0);
+        step(&mut app, 10);
+        assert_eq!(state(&app), GameState::Playing);
+
+        kill_player(&mut app, 1);
+        step_until(&mut app, GameState::GameOver, 10);
+    }
+
+    #[test]
+    fn clearing_every_wave_is_a_victory() {
+        let mut app = app();
+        start_run(&mut app, 7, GameMode::Campaign);
+
+        // the campaign manifest lists five waves, the last one is the boss
+        for wave in 0..=5 {
+            step(&mut app, 3);
+            if wave == 5 {
+                break;
+            }
+
+            assert_eq!(state(&app), GameState::LoadWaveState);
+            assert_eq!(app.world.resource::<Global>().current_wave(), wave);
+
+            step_until(&mut app, GameState::Playing, MAX_LOADING_WAVE_FRAMES);
+            clear_wave(&mut app);
+        }
+
+        assert_eq!(state(&app), GameState::Victory);
+        assert_eq!(count::<With<Ship>>(&mut app), 0);
+    }
+
+    #[test]
+    fn a_diving_alien_rams_the_ship() {
+        let mut app = app();
+        start_run(&mut app, 7, GameMode::Campaign);
+        step_until(&mut app, GameState::Playing, MAX_LOADING_WAVE_FRAMES);
+
+        // a Kiro already on its way through the ship
+        let ship = app.world.query_filtered::<&Transform, With<Ship>>().single(&app.world).translation;
+        let kiro = app
+            .world
+            .resource::<Archetypes>()
+            .get("Kiro", app.world.resource::<Assets<Roster>>())
+            .expect("the roster has a Kiro")
+            .clone();
+        let tuning = AlienTuning {
+            speed: 1.0,
+            fire_rate: 1.0,
+            health: None,
+        };
+
+        let mut queue = CommandQueue::default();
+        let mut commands = Commands::new(&mut queue, &app.world);
+        let alien = spawn_alien(&mut commands, &kiro, ship.truncate(), None, tuning, &mut ChaCha8Rng::seed_from_u64(0));
+        commands.entity(alien).insert(Diving);
+        queue.apply(&mut app.world);
+
+        step(&mut app, 2);
+        assert!(app.world.get_entity(alien).is_none());
+        let health = app.world.query_filtered::<&Health, With<Ship>>().single(&app.world);
+        assert_eq!(health.0, INITIAL_HEALTH_POINTS - 1);
+    }
+}
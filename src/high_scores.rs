@@ -0,0 +1,225 @@
+use bevy::prelude::*;
+use iyes_loopless::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    controls::{Action, ActionState, Controls},
+    replay::ReplayPlayback,
+    storage, GameState, Scoreboard,
+};
+
+const HIGH_SCORES_KEY: &str = "high_scores.ron";
+const MAX_HIGH_SCORES: usize = 10;
+const INITIALS_LENGTH: usize = 3;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct HighScore {
+    pub initials: String,
+    pub score: u32,
+}
+
+/*
+  The top scores across runs, highest first
+*/
+#[derive(Serialize, Deserialize, Default)]
+pub struct HighScores {
+    entries: Vec<HighScore>,
+}
+
+impl HighScores {
+    pub fn load() -> HighScores {
+        storage::load(HIGH_SCORES_KEY)
+            .and_then(|saved| ron::de::from_str(&saved).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) {
+        let result = ron::ser::to_string(self)
+            .map_err(|error| error.to_string())
+            .and_then(|serialized| storage::save(HIGH_SCORES_KEY, &serialized));
+
+        if let Err(error) = result {
+            warn!("Failed to save high scores: {}", error);
+        }
+    }
+
+    pub fn qualifies(&self, score: u32) -> bool {
+        score > 0 && (self.entries.len() < MAX_HIGH_SCORES || self.entries.iter().any(|entry| score > entry.score))
+    }
+
+    pub fn insert(&mut self, entry: HighScore) {
+        // ties go to the older score
+        let position = self.entries.iter().position(|existing| entry.score > existing.score).unwrap_or(self.entries.len());
+        self.entries.insert(position, entry);
+        self.entries.truncate(MAX_HIGH_SCORES);
+    }
+
+    pub fn table_text(&self) -> String {
+        if self.entries.is_empty() {
+            return "No scores yet\n".to_string();
+        }
+
+        self.entries
+            .iter()
+            .enumerate()
+            .map(|(rank, entry)| format!("{:>2}. {}  {:>6}\n", rank + 1, entry.initials, entry.score))
+            .collect()
+    }
+}
+
+/*
+  Present while the player enters their initials for a qualifying score, arcade style:
+  Up and Down cycle the letter, Confirm takes it and Back goes back one.
+  The game over and victory screens ignore their own actions while it exists.
+*/
+pub struct InitialsEntry {
+    score: u32,
+    initials: String,
+    letter: char,
+}
+
+impl InitialsEntry {
+    fn cycle(&mut self, forward: bool) {
+        let index = self.letter as u8 - b'A';
+        let index = if forward { (index + 1) % 26 } else { (index + 25) % 26 };
+        self.letter = (b'A' + index) as char;
+    }
+}
+
+#[derive(Component)]
+struct InitialsPrompt;
+
+pub struct HighScoresPlugin;
+
+impl Plugin for HighScoresPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(HighScores::load())
+            .add_enter_system(GameState::GameOver, start_initials_entry)
+            .add_enter_system(GameState::Victory, start_initials_entry)
+            .add_system(update_initials_entry.run_if_resource_exists::<InitialsEntry>())
+            .add_exit_system(GameState::GameOver, cleanup_initials_entry)
+            .add_exit_system(GameState::Victory, cleanup_initials_entry);
+    }
+}
+
+fn prompt_text(entry: &InitialsEntry, controls: &Controls) -> String {
+    let blanks = "_".repeat(INITIALS_LENGTH - entry.initials.len() - 1);
+    format!(
+        "NEW HIGH SCORE! {}[{}]{}  [{}] [{}] Letter  [{}] Next  [{}] Back",
+        entry.initials,
+        entry.letter,
+        blanks,
+        controls.describe(Action::Up),
+        controls.describe(Action::Down),
+        controls.describe(Action::Confirm),
+        controls.describe(Action::Back),
+    )
+}
+
+fn start_initials_entry(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    scoreboard: Option<Res<Scoreboard>>,
+    high_scores: Res<HighScores>,
+    controls: Res<Controls>,
+    playback: Option<Res<ReplayPlayback>>,
+) {
+    // replays don't get to enter the table again
+    if playback.is_some() {
+        return;
+    }
+
+    let score = match scoreboard {
+        Some(scoreboard) if high_scores.qualifies(scoreboard.score) => scoreboard.score,
+        _ => return,
+    };
+    let entry = InitialsEntry {
+        score,
+        initials: String::new(),
+        letter: 'A',
+    };
+
+    commands
+        .spawn()
+        .insert(InitialsPrompt)
+        .insert(Name::new("Initials Prompt"))
+        .insert_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    bottom: Val::Px(40.0),
+                    ..default()
+                },
+                size: Size::new(Val::Percent(100.0), Val::Auto),
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            color: Color::NONE.into(),
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle::from_section(
+                prompt_text(&entry, &controls),
+                TextStyle {
+                    font_size: 35.0,
+                    color: Color::rgb(1.0, 1.0, 0.0),
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                },
+            ));
+        });
+
+    commands.insert_resource(entry);
+}
+
+fn update_initials_entry(
+    mut commands: Commands,
+    actions: Res<ActionState>,
+    controls: Res<Controls>,
+    mut entry: ResMut<InitialsEntry>,
+    mut high_scores: ResMut<HighScores>,
+    prompt_query: Query<(Entity, &Children), With<InitialsPrompt>>,
+    mut text_query: Query<&mut Text>,
+) {
+    if actions.just_pressed(Action::Up) {
+        entry.cycle(true);
+    } else if actions.just_pressed(Action::Down) {
+        entry.cycle(false);
+    } else if actions.just_pressed(Action::Confirm) {
+        let letter = entry.letter;
+        entry.initials.push(letter);
+    } else if actions.just_pressed(Action::Back) {
+        if let Some(letter) = entry.initials.pop() {
+            entry.letter = letter;
+        }
+    }
+
+    let submitted = entry.initials.len() == INITIALS_LENGTH;
+
+    for (prompt, children) in prompt_query.iter() {
+        if submitted {
+            commands.entity(prompt).despawn_recursive();
+            continue;
+        }
+        for child in children.iter() {
+            if let Ok(mut text) = text_query.get_mut(*child) {
+                text.sections[0].value = prompt_text(&entry, &controls);
+            }
+        }
+    }
+
+    if submitted {
+        high_scores.insert(HighScore {
+            initials: entry.initials.clone(),
+            score: entry.score,
+        });
+        high_scores.save();
+        commands.remove_resource::<InitialsEntry>();
+    }
+}
+
+fn cleanup_initials_entry(mut commands: Commands, prompt_query: Query<Entity, With<InitialsPrompt>>) {
+    for prompt in prompt_query.iter() {
+        commands.entity(prompt).despawn_recursive();
+    }
+    commands.remove_resource::<InitialsEntry>();
+}
@@ -1,18 +1,42 @@
+// bevy systems take their queries and resources as arguments, so these grow with the game
+#![allow(clippy::too_many_arguments, clippy::type_complexity)]
+
 use std::{time::Duration};
 
-use bevy::prelude::*;
+use bevy::{audio::AudioSink, prelude::*};
 use bevy_tweening::TweeningPlugin;
 use iyes_loopless::prelude::*;
 
 mod player;
-use player::{PlayerPlugin, Ship};
+use player::{FerrisState, Player, PlayerPlugin, Ship, MAX_PLAYERS};
 
 mod aliens;
 use aliens::{Alien, AliensPlugin};
 
+mod archetypes;
+use archetypes::ArchetypesPlugin;
+
+mod boss;
+use boss::{BossExplosion, BossPlugin};
+
+mod bunkers;
+use bunkers::BunkersPlugin;
+
+mod divers;
+use divers::{Diving, DiversPlugin};
+
+mod paths;
+use paths::PathsPlugin;
+
+mod patterns;
+use patterns::PatternsPlugin;
+
 mod shared;
 use shared::*;
 
+mod controls;
+use controls::{ControlsPlugin, ControlsScreen};
+
 mod debug;
 use debug::DebugPlugin;
 
@@ -22,10 +46,39 @@ use gameover::GameOverPlugin;
 mod menu;
 use menu::MenuPlugin;
 
+mod high_scores;
+use high_scores::HighScoresPlugin;
+
+mod storage;
+
 mod hall_of_fame;
 use hall_of_fame::HallOfFamePlugin;
 
+mod powerups;
+use powerups::PowerUpsPlugin;
+
+mod pause;
+use pause::{Paused, PausePlugin};
+
+mod replay;
+use replay::ReplayPlugin;
+
+mod rng;
+use rng::GameRng;
+
+mod simulation;
+use simulation::{SimulationApp, SimulationPhase, SimulationPlugin};
+
+mod spatial_grid;
+
+#[cfg(test)]
+mod headless;
+
+mod weapons;
+use weapons::WeaponsPlugin;
+
 mod waves;
+use waves::{Wave, WaveManifest, Waves, WavesPlugin};
 
 const LOAD_WAVE_DURATION_IN_SECONDS: f32 = 3.0;
 
@@ -39,14 +92,61 @@ enum GameState {
     GameOver,      // Player is frozen and enemies have been despawned (press r to restart)
     LoadWaveState, // Load enemies into the scene (player and enemies cannot shoot)
     Victory,       // You win!
+    Paused,        // Everything is frozen, see pause::Paused for the state to return to
 }
 
 #[derive(Component)]
 struct Background;
 
+#[derive(Component)]
+struct WaveIntro;
+
+/*
+  The track that is currently looping, so waves sharing music don't restart it
+*/
+struct WaveMusic {
+    path: String,
+    sink: Handle<AudioSink>,
+}
+
 #[derive(Component)]
 pub struct Scoreboard {
     score: u32,
+    // what each player scored, score is their sum
+    players: Vec<u32>,
+}
+
+impl Scoreboard {
+    fn new(players: usize) -> Scoreboard {
+        Scoreboard {
+            score: 0,
+            players: vec![0; players],
+        }
+    }
+
+    pub fn add(&mut self, player: Player, points: u32) {
+        self.score += points;
+        if let Some(score) = self.players.get_mut(player.0) {
+            *score += points;
+        }
+    }
+
+    /*
+      Each player's share, left out when there is only one player
+    */
+    fn players_text(&self) -> String {
+        if self.players.len() < 2 {
+            return String::new();
+        }
+
+        let scores: Vec<String> = self
+            .players
+            .iter()
+            .enumerate()
+            .map(|(index, score)| format!("{} {}", Player(index).label(), score))
+            .collect();
+        format!("  ({})", scores.join("  "))
+    }
 }
 
 #[derive(Component)]
@@ -57,9 +157,39 @@ pub enum TurboStages {
     FixedUpdate,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameMode {
+    Campaign,
+    Endless { seed: u64 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Difficulty {
+    Casual, // aliens that reach the invasion line damage the ship
+    Normal, // aliens that reach the invasion line end the run
+}
+
+impl Difficulty {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Difficulty::Casual => "Casual",
+            Difficulty::Normal => "Normal",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameOverReason {
+    ShipDestroyed,
+    Invaded,
+}
+
 pub struct Global {
     is_playing: bool,
     wave: Option<u32>,
+    mode: GameMode,
+    difficulty: Difficulty,
+    players: usize,
 }
 
 impl Global {
@@ -72,9 +202,44 @@ impl Global {
         self.wave.unwrap()
     }
 
-    pub fn start_playing(&mut self) {
+    pub fn mode(&self) -> GameMode {
+        self.mode
+    }
+
+    pub fn difficulty(&self) -> Difficulty {
+        self.difficulty
+    }
+
+    pub fn set_difficulty(&mut self, difficulty: Difficulty) {
+        self.difficulty = difficulty;
+    }
+
+    pub fn toggle_difficulty(&mut self) {
+        self.difficulty = match self.difficulty {
+            Difficulty::Casual => Difficulty::Normal,
+            Difficulty::Normal => Difficulty::Casual,
+        };
+    }
+
+    pub fn players(&self) -> usize {
+        self.players
+    }
+
+    pub fn set_players(&mut self, players: usize) {
+        self.players = players.clamp(1, MAX_PLAYERS);
+    }
+
+    /*
+      Cycles between one player and local co-op
+    */
+    pub fn toggle_players(&mut self) {
+        self.players = self.players % MAX_PLAYERS + 1;
+    }
+
+    pub fn start_playing(&mut self, mode: GameMode) {
         self.is_playing = true;
         self.wave = Some(0);
+        self.mode = mode;
     }
 
     pub fn wave_cleared(&mut self) {
@@ -90,55 +255,93 @@ impl Global {
 fn main() {
     console_error_panic_hook::set_once();
 
-    let mut fixedupdate = SystemStage::parallel();
-    fixedupdate.add_system(update_bullets.run_in_state(GameState::Playing));
-    fixedupdate.add_system(check_gameover.run_in_state(GameState::Playing));
-    fixedupdate.add_system(update_shooting_cooldowns.run_in_state(GameState::Playing));
-    fixedupdate.add_system(update_load_wave.run_in_state(GameState::LoadWaveState));
-    fixedupdate.add_system(update_timed);
-
-    App::new()
-        .insert_resource(WindowDescriptor {
-            title: "Turbo".to_string(),
-            width: WINDOW_WIDTH,
-            height: WINDOW_HEIGHT,
-            resizable: false,
-            // position: WindowPosition::Centered(MonitorSelection::Number(0)),
-            canvas: Some("#bevy".to_owned()),
-            ..default()
-        })
-        .add_loopless_state(GameState::Menu)
-        .add_plugins(DefaultPlugins)
+    let mut app = App::new();
+    app.insert_resource(WindowDescriptor {
+        title: "Turbo".to_string(),
+        width: WINDOW_WIDTH,
+        height: WINDOW_HEIGHT,
+        resizable: false,
+        // position: WindowPosition::Centered(MonitorSelection::Number(0)),
+        canvas: Some("#bevy".to_owned()),
+        ..default()
+    })
+    .add_plugins(DefaultPlugins)
+    .add_plugin(AnimationPlugin::default());
+
+    add_game(&mut app);
+    app.run();
+}
+
+/*
+  Everything but the window, renderer and audio output, so the game also runs headless
+*/
+fn add_game(app: &mut App) {
+    app.add_loopless_state(GameState::Menu)
+        .add_plugin(SimulationPlugin)
         // startup
         .add_startup_system(load_assets_and_animations.before(setup))
         .add_startup_system(setup)
         // fixed update
-        .add_stage_before(
-            CoreStage::Update,
-            "Main Fixed TimeStep",
-            FixedTimestepStage::from_stage(Duration::from_secs_f32(TIME_STEP), fixedupdate),
-        )
+        .add_simulation_system(SimulationPhase::Ai, update_shooting_cooldowns.run_in_state(GameState::Playing))
+        .add_simulation_system(SimulationPhase::Movement, update_bullets.run_in_state(GameState::Playing))
+        .add_simulation_system(SimulationPhase::Collision, detect_collisions.run_in_state(GameState::Playing))
+        .add_simulation_system(SimulationPhase::Damage, check_invasion.run_in_state(GameState::Playing))
+        .add_simulation_system(SimulationPhase::Damage, check_gameover.run_in_state(GameState::Playing))
+        .add_simulation_system(SimulationPhase::Damage, check_wave_end.run_in_state(GameState::Playing))
+        .add_simulation_system(SimulationPhase::Cleanup, despawn_offscreen_bullets.run_in_state(GameState::Playing))
+        .add_simulation_system(SimulationPhase::Cleanup, update_load_wave.run_in_state(GameState::LoadWaveState))
+        .add_simulation_system(SimulationPhase::Cleanup, update_timed.run_not_in_state(GameState::Paused))
         // resources
         .insert_resource(Animations::new())
         .insert_resource(Sprites::new())
+        .insert_resource(GameRng::from_entropy())
+        .init_resource::<BulletCulling>()
+        .init_resource::<CollisionGrid>()
+        .add_event::<BulletHitAlien>()
+        .add_event::<BulletHitShip>()
+        .add_event::<BulletHitBunker>()
+        .add_event::<BulletHitSeeker>()
+        .add_event::<AlienHitShip>()
+        .add_event::<AlienDestroyed>()
         // plugins
         .add_plugin(TweeningPlugin)
+        .add_plugin(WavesPlugin)
+        .add_plugin(ArchetypesPlugin)
         .add_plugin(DebugPlugin)
         .add_plugin(MenuPlugin)
         .add_plugin(GameOverPlugin)
         .add_plugin(HallOfFamePlugin)
+        .add_plugin(HighScoresPlugin)
+        .add_plugin(ControlsPlugin)
+        .add_plugin(ReplayPlugin)
+        .add_plugin(WeaponsPlugin)
         .add_plugin(PlayerPlugin)
         .add_plugin(AliensPlugin)
-        .add_plugin(AnimationPlugin::default())
-        .add_system(check_wave_end.run_in_state(GameState::Playing))
+        .add_plugin(BossPlugin)
+        .add_plugin(DiversPlugin)
+        .add_plugin(PathsPlugin)
+        .add_plugin(PatternsPlugin)
+        .add_plugin(BunkersPlugin)
+        .add_plugin(PowerUpsPlugin)
+        .add_plugin(PausePlugin)
         .add_enter_system(
             GameState::LoadWaveState,
-            setup_load_wave,
+            setup_load_wave.run_unless_resource_exists::<Paused>(),
         )
+        .add_enter_system(GameState::LoadWaveState, show_wave_intro.run_unless_resource_exists::<Paused>())
+        .add_exit_system(GameState::LoadWaveState, hide_wave_intro.run_unless_resource_exists::<Paused>())
+        .add_enter_system(GameState::GameOver, stop_wave_music)
+        .add_enter_system(GameState::Victory, stop_wave_music)
+        .add_enter_system(GameState::Menu, stop_wave_music)
         .add_system(update_scoreboard.run_in_state(GameState::Playing))
-        .add_system(update_explosions)
-        .add_system(bevy::window::close_on_esc)
-        .run();
+        .add_system(update_explosions.run_not_in_state(GameState::Paused))
+        // escape pauses a run instead of quitting
+        .add_system(bevy::window::close_on_esc.run_if(can_quit));
+}
+
+fn can_quit(state: Res<CurrentState<GameState>>, controls_screen: Option<Res<ControlsScreen>>) -> bool {
+    // escape backs out of the controls screen
+    controls_screen.is_none() && matches!(state.0, GameState::Menu | GameState::GameOver | GameState::Victory)
 }
 
 fn load_assets_and_animations(
@@ -168,6 +371,9 @@ fn setup(mut commands: Commands, sprites: Res<Sprites>) {
     commands.insert_resource(Global {
         is_playing: false,
         wave: None,
+        mode: GameMode::Campaign,
+        difficulty: Difficulty::Normal,
+        players: 1,
     });
 
     commands.spawn_bundle(Camera2dBundle {
@@ -179,7 +385,7 @@ fn setup(mut commands: Commands, sprites: Res<Sprites>) {
     });
 
     // background
-    commands.spawn().insert_bundle(SpriteBundle {
+    commands.spawn().insert(Background).insert_bundle(SpriteBundle {
         transform: Transform {
             translation: Vec3::new(0.0, 0.0, BACKGROUND_LEVEL),
             ..default()
@@ -229,20 +435,67 @@ fn check_gameover(ship_query: Query<&Health, With<Ship>>, game_state: Res<Curren
         return;
     }
 
-    let ship_health = ship_query.single();
-
-    if ship_health.0 == 0 {
+    // the run goes on while any ship is still up
+    if !ship_query.is_empty() && ship_query.iter().all(|health| health.0 == 0) {
+        commands.insert_resource(GameOverReason::ShipDestroyed);
         commands.insert_resource(NextState(GameState::GameOver));
     }
 }
 
+fn check_invasion(
+    // divers fly past the ships on purpose
+    alien_query: Query<(Entity, &Transform, &Collider), (With<Alien>, Without<Diving>)>,
+    mut ship_query: Query<(&mut Health, &mut FerrisState), With<Ship>>,
+    global: Res<Global>,
+    mut commands: Commands,
+) {
+    for (alien_entity, transform, collider) in alien_query.iter() {
+        if transform.translation.y - collider.size.y / 2. > INVASION_LINE {
+            continue;
+        }
+
+        match global.difficulty() {
+            Difficulty::Normal => {
+                commands.insert_resource(GameOverReason::Invaded);
+                commands.insert_resource(NextState(GameState::GameOver));
+                return;
+            }
+            Difficulty::Casual => {
+                commands.entity(alien_entity).despawn_recursive();
+
+                // every ship that is still up takes the damage
+                for (mut health, mut ferris_state) in ship_query.iter_mut() {
+                    if health.0 == 0 {
+                        continue;
+                    }
+                    health.0 = health.0.saturating_sub(INVASION_DAMAGE);
+
+                    if health.0 == 0 {
+                        *ferris_state = FerrisState::DEAD;
+                    }
+                }
+            }
+        }
+    }
+}
+
 fn check_wave_end(
     alien_query: Query<With<Alien>>, 
+    explosion_query: Query<With<BossExplosion>>,
     bullet_query: Query<Entity, With<Bullet>>, 
+    ship_query: Query<&Health, With<Ship>>,
+    next_state: Option<Res<NextState<GameState>>>,
     mut commands: Commands, 
     mut global: ResMut<Global>
 ) {
-    if alien_query.is_empty() {
+    // the last alien can take the last ship with it, losing the run wins over clearing the wave
+    let ships_down = !ship_query.is_empty() && ship_query.iter().all(|health| health.0 == 0);
+    if ships_down || matches!(next_state.as_deref(), Some(NextState(GameState::GameOver))) {
+        return;
+    }
+
+    // a boss has to finish blowing up first
+    if alien_query.is_empty() && explosion_query.is_empty() {
         for bullet in bullet_query.iter() {
             commands.entity(bullet).despawn();
         }
@@ -255,6 +508,7 @@ fn check_wave_end(
 fn update_scoreboard(scoreboard: Res<Scoreboard>, mut query: Query<&mut Text, With<Scoreboard>>) {
     let mut score_text = query.single_mut();
     score_text.sections[1].value = scoreboard.score.to_string();
+    score_text.sections[2].value = scoreboard.players_text();
 }
 
 fn update_bullets(mut bullet_query: Query<(&mut Transform, &Velocity), With<Bullet>>) {
@@ -278,10 +532,16 @@ fn setup_load_wave(
     mut commands: Commands, 
     global: ResMut<Global>,
     asset_server: Res<AssetServer>,
-    scoreboard_query: Query<Entity, With<Scoreboard>>
+    scoreboard_query: Query<Entity, With<Scoreboard>>,
+    waves: Res<Waves>,
+    wave_manifests: Res<Assets<WaveManifest>>,
+    wave_assets: Res<Assets<Wave>>,
 ) {
+    let load_wave_duration = waves.current(&global, &wave_manifests, &wave_assets)
+        .map_or(LOAD_WAVE_DURATION_IN_SECONDS, |wave| wave.load_duration);
+
     commands.insert_resource(LoadWaveTimer(Timer::from_seconds(
-        LOAD_WAVE_DURATION_IN_SECONDS + 2.0,
+        load_wave_duration + 2.0,
         false,
     )));
 
@@ -311,6 +571,11 @@ fn setup_load_wave(
                     color: SCORE_COLOR,
                     font: asset_server.load("fonts/FiraSans-Bold.ttf"),
                 }),
+                TextSection::new("", TextStyle {
+                    font_size: SCOREBOARD_FONT_SIZE,
+                    color: SCORE_COLOR,
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                }),
             ])
             .with_style(Style {
                 position_type: PositionType::Absolute,
@@ -321,8 +586,8 @@ fn setup_load_wave(
                 },
                 ..default()
             }),
-        ).insert(Scoreboard { score: 0 });
-    commands.insert_resource(Scoreboard { score: 0 })
+        ).insert(Scoreboard::new(global.players()));
+    commands.insert_resource(Scoreboard::new(global.players()))
 }
 
 fn update_load_wave(mut commands: Commands, mut timer: ResMut<LoadWaveTimer>) {
@@ -332,3 +597,97 @@ fn update_load_wave(mut commands: Commands, mut timer: ResMut<LoadWaveTimer>) {
         commands.insert_resource(NextState(GameState::Playing));
     }
 }
+
+fn show_wave_intro(
+    mut commands: Commands,
+    global: Res<Global>,
+    asset_server: Res<AssetServer>,
+    audio: Res<Audio>,
+    audio_sinks: Res<Assets<AudioSink>>,
+    sprites: Res<Sprites>,
+    waves: Res<Waves>,
+    wave_manifests: Res<Assets<WaveManifest>>,
+    wave_assets: Res<Assets<Wave>>,
+    wave_music: Option<Res<WaveMusic>>,
+    mut background_query: Query<&mut Handle<Image>, With<Background>>,
+) {
+    let wave = match waves.current(&global, &wave_manifests, &wave_assets) {
+        Some(wave) => wave,
+        None => return,
+    };
+
+    for mut background in background_query.iter_mut() {
+        *background = match &wave.background {
+            Some(path) => asset_server.load(path.as_str()),
+            None => sprites.get("SPACE_BACKGROUND"),
+        };
+    }
+
+    let current_music = wave_music.as_ref().map(|music| music.path.as_str());
+    if current_music != wave.music.as_deref() {
+        if let Some(sink) = wave_music.and_then(|music| audio_sinks.get(&music.sink)) {
+            sink.pause();
+        }
+        commands.remove_resource::<WaveMusic>();
+
+        if let Some(path) = &wave.music {
+            let sink = audio.play_with_settings(asset_server.load(path.as_str()), PlaybackSettings::LOOP);
+            commands.insert_resource(WaveMusic {
+                path: path.clone(),
+                sink: audio_sinks.get_handle(sink),
+            });
+        }
+    }
+
+    commands
+        .spawn()
+        .insert(WaveIntro)
+        .insert(Name::new("Wave Intro"))
+        .insert_bundle(
+            TextBundle::from_sections([
+                TextSection::new(
+                    format!("Wave {}: {}\n", global.current_wave() + 1, wave.name),
+                    TextStyle {
+                        font_size: 50.0,
+                        color: Color::rgb(1.0, 1.0, 1.0),
+                        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    },
+                ),
+                TextSection::new(
+                    wave.intro.clone(),
+                    TextStyle {
+                        font_size: 30.0,
+                        color: Color::rgb(0.7, 0.7, 0.7),
+                        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    },
+                ),
+            ])
+            .with_style(Style {
+                position_type: PositionType::Relative,
+                margin: UiRect {
+                    top: Val::Auto,
+                    left: Val::Auto,
+                    right: Val::Auto,
+                    bottom: Val::Auto,
+                },
+                align_self: AlignSelf::Center,
+                ..default()
+            }),
+        );
+}
+
+/*
+  The run is over, the next one starts its music from the beginning
+*/
+fn stop_wave_music(mut commands: Commands, audio_sinks: Res<Assets<AudioSink>>, wave_music: Option<Res<WaveMusic>>) {
+    if let Some(sink) = wave_music.and_then(|music| audio_sinks.get(&music.sink)) {
+        sink.stop();
+    }
+    commands.remove_resource::<WaveMusic>();
+}
+
+fn hide_wave_intro(mut commands: Commands, query: Query<Entity, With<WaveIntro>>) {
+    for intro in query.iter() {
+        commands.entity(intro).despawn();
+    }
+}
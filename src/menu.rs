@@ -1,30 +1,227 @@
-use bevy::prelude::*;
+use bevy::{ecs::system::SystemParam, prelude::*};
 use iyes_loopless::prelude::*;
+use rand::random;
+use std::marker::PhantomData;
 
-use crate::{GameState, Global};
+use crate::{
+    archetypes::{Archetypes, Roster},
+    controls::{open_controls_screen, Action, ActionState, Controls, ControlsScreen},
+    high_scores::HighScores,
+    replay::{start_replay, LastReplay, Replay},
+    rng::GameRng,
+    waves::{WaveManifest, Waves},
+    weapons::{Arsenal, Weapons},
+    GameMode, GameState, Global,
+};
 
 #[derive(Component)]
 struct Menu;
 
+#[derive(Component)]
+struct MenuTitle;
+
+#[derive(Component)]
+struct HighScoreTable;
+
+// as many as u64::MAX has, so any seed shown on the game over screen can be typed back in
+const MAX_SEED_DIGITS: usize = 20;
+const DIGIT_KEYS: [(KeyCode, char); 10] = [
+    (KeyCode::Key0, '0'), (KeyCode::Key1, '1'), (KeyCode::Key2, '2'), (KeyCode::Key3, '3'), (KeyCode::Key4, '4'),
+    (KeyCode::Key5, '5'), (KeyCode::Key6, '6'), (KeyCode::Key7, '7'), (KeyCode::Key8, '8'), (KeyCode::Key9, '9'),
+];
+
+/*
+  The seed typed into the menu, runs use a random seed when it is empty.
+  Twenty digits can go past u64::MAX, a seed like that can't be confirmed.
+*/
+#[derive(Default)]
+struct SeedEntry {
+    typing: bool,
+    digits: String,
+}
+
+/*
+  What a run needs before it can start
+*/
+#[derive(SystemParam)]
+struct RunAssets<'w, 's> {
+    asset_server: Res<'w, AssetServer>,
+    waves: Res<'w, Waves>,
+    wave_manifests: Res<'w, Assets<WaveManifest>>,
+    weapons: Res<'w, Weapons>,
+    arsenals: Res<'w, Assets<Arsenal>>,
+    archetypes: Res<'w, Archetypes>,
+    rosters: Res<'w, Assets<Roster>>,
+    #[system_param(ignore)]
+    marker: PhantomData<&'s ()>,
+}
+
+impl RunAssets<'_, '_> {
+    // waves, weapons and the alien roster are loaded asynchronously
+    fn is_loaded(&self) -> bool {
+        self.waves.is_loaded(&self.asset_server, &self.wave_manifests)
+            && self.weapons.is_loaded(&self.arsenals)
+            && self.archetypes.is_loaded(&self.rosters)
+    }
+}
+
+/*
+  The menu text and the hall of fame table shown in its place
+*/
+#[derive(SystemParam)]
+struct MenuScreens<'w, 's> {
+    text_query: Query<'w, 's, &'static mut Text, With<MenuTitle>>,
+    title_query: Query<'w, 's, &'static mut Visibility, (With<MenuTitle>, Without<HighScoreTable>)>,
+    table_query: Query<'w, 's, &'static mut Visibility, (With<HighScoreTable>, Without<MenuTitle>)>,
+}
+
+impl MenuScreens<'_, '_> {
+    fn showing_table(&self) -> bool {
+        self.table_query.iter().any(|visibility| visibility.is_visible)
+    }
+
+    fn toggle_table(&mut self) {
+        for mut visibility in self.title_query.iter_mut() {
+            visibility.is_visible = !visibility.is_visible;
+        }
+        for mut visibility in self.table_query.iter_mut() {
+            visibility.is_visible = !visibility.is_visible;
+        }
+    }
+}
+
+/*
+  What the menu cursor can land on, in the order they are listed
+*/
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum MenuItem {
+    Play,
+    Endless,
+    HallOfFame,
+    WatchLastRun,
+    Controls,
+    Difficulty,
+    Players,
+    Seed,
+}
+
+impl MenuItem {
+    const ALL: [MenuItem; 8] = [
+        MenuItem::Play,
+        MenuItem::Endless,
+        MenuItem::HallOfFame,
+        MenuItem::WatchLastRun,
+        MenuItem::Controls,
+        MenuItem::Difficulty,
+        MenuItem::Players,
+        MenuItem::Seed,
+    ];
+}
+
+/*
+  The item Confirm picks, moved with Up and Down
+*/
+#[derive(Default)]
+struct MenuCursor(usize);
+
+impl SeedEntry {
+    fn seed(&self) -> Option<u64> {
+        if self.digits.is_empty() {
+            return Some(random());
+        }
+        self.digits.parse().ok()
+    }
+
+    fn is_valid(&self) -> bool {
+        self.digits.is_empty() || self.digits.parse::<u64>().is_ok()
+    }
+}
+
 pub struct MenuPlugin;
 
 impl Plugin for MenuPlugin {
     fn build(&self, app: &mut App) {
         app
             .add_enter_system(GameState::Menu, Menu::initialize)
-            .add_system(Menu::update.run_in_state(GameState::Menu))
+            .add_system(
+                Menu::update
+                    .run_in_state(GameState::Menu)
+                    .run_unless_resource_exists::<ControlsScreen>(),
+            )
             .add_exit_system(GameState::Menu, Menu::cleanup);
     }
 }
 
 impl Menu {
+    // the title and subtitle come before the items
+    const FIRST_ITEM_SECTION: usize = 2;
+
+    fn item_text(item: MenuItem, selected: bool, global: &Global, seed_entry: &SeedEntry, controls: &Controls) -> String {
+        let label = match item {
+            MenuItem::Play => "Play".to_string(),
+            MenuItem::Endless => "Endless".to_string(),
+            MenuItem::HallOfFame => "Hall of Fame".to_string(),
+            MenuItem::WatchLastRun => "Watch Last Run".to_string(),
+            MenuItem::Controls => "Controls".to_string(),
+            MenuItem::Difficulty => format!("Difficulty: {}", global.difficulty().name()),
+            MenuItem::Players => match global.players() {
+                1 => "Players: 1".to_string(),
+                players => format!("Players: {} (co-op)", players),
+            },
+            MenuItem::Seed => Menu::seed_text(seed_entry, controls),
+        };
+
+        if selected {
+            format!("→ {} ←\n", label)
+        } else {
+            format!("{}\n", label)
+        }
+    }
+
+    fn seed_text(entry: &SeedEntry, controls: &Controls) -> String {
+        match (entry.typing, entry.digits.is_empty()) {
+            (true, _) if !entry.is_valid() => format!("Seed: {}_  Too large, the most is {}", entry.digits, u64::MAX),
+            (true, _) => format!(
+                "Seed: {}_  [{}] Confirm  [{}] Random",
+                entry.digits,
+                controls.describe(Action::Confirm),
+                controls.describe(Action::Back),
+            ),
+            (false, true) => "Seed: Random".to_string(),
+            (false, false) => format!("Seed: {}", entry.digits),
+        }
+    }
+
+    fn hint_text(controls: &Controls) -> String {
+        format!(
+            "\n[{}] [{}] Select   [{}] Choose",
+            controls.describe(Action::Up),
+            controls.describe(Action::Down),
+            controls.describe(Action::Confirm),
+        )
+    }
+
+    fn refresh_items(text: &mut Text, cursor: &MenuCursor, global: &Global, seed_entry: &SeedEntry, controls: &Controls) {
+        for (index, item) in MenuItem::ALL.into_iter().enumerate() {
+            text.sections[Menu::FIRST_ITEM_SECTION + index].value =
+                Menu::item_text(item, index == cursor.0, global, seed_entry, controls);
+        }
+    }
+
     fn initialize(
         mut commands: Commands,
-        asset_server: Res<AssetServer>
+        asset_server: Res<AssetServer>,
+        global: Res<Global>,
+        high_scores: Res<HighScores>,
+        controls: Res<Controls>,
     ) {
+        let seed_entry = SeedEntry::default();
+        let cursor = MenuCursor::default();
+
         commands
             .spawn()
             .insert(Menu)
+            .insert(MenuTitle)
             .insert(Name::new("Menu"))
             .insert_bundle(
                 TextBundle::from_sections([
@@ -44,11 +241,67 @@ impl Menu {
                             font: asset_server.load("fonts/FiraSans-Bold.ttf"),
                         },
                     ),
+                ]
+                .into_iter()
+                .chain(MenuItem::ALL.into_iter().enumerate().map(|(index, item)| {
+                    TextSection::new(
+                        Menu::item_text(item, index == cursor.0, &global, &seed_entry, &controls),
+                        TextStyle {
+                            font_size: if item == MenuItem::Play { 40.0 } else { 30.0 },
+                            color: if item == MenuItem::Play { Color::rgb(1.0, 1.0, 1.0) } else { Color::rgb(0.7, 0.7, 0.7) },
+                            font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                        },
+                    )
+                }))
+                .chain([TextSection::new(
+                    Menu::hint_text(&controls),
+                    TextStyle {
+                        font_size: 25.0,
+                        color: Color::rgb(0.5, 0.5, 0.5),
+                        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    },
+                )]),
+                )
+                .with_style(Style {
+                    position_type: PositionType::Relative,
+                    margin: UiRect {
+                        top: Val::Auto,
+                        left: Val::Auto,
+                        right: Val::Auto,
+                        bottom: Val::Auto,
+                    },
+                    align_self: AlignSelf::Center,
+                    ..default()
+                }),
+            );
+        commands
+            .spawn()
+            .insert(Menu)
+            .insert(HighScoreTable)
+            .insert(Name::new("Menu High Scores"))
+            .insert_bundle(
+                TextBundle::from_sections([
+                    TextSection::new(
+                        "Hall of Fame\n\n",
+                        TextStyle {
+                            font_size: 70.0,
+                            color: Color::rgb(1.0, 1.0, 0.0),
+                            font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                        },
+                    ),
                     TextSection::new(
-                        "→ Press [space] to play ←",
+                        high_scores.table_text(),
                         TextStyle {
-                            font_size: 40.0,
+                            font_size: 30.0,
                             color: Color::rgb(1.0, 1.0, 1.0),
+                            font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+                        },
+                    ),
+                    TextSection::new(
+                        format!("\n[{}] Back", controls.describe(Action::Back)),
+                        TextStyle {
+                            font_size: 30.0,
+                            color: Color::rgb(0.7, 0.7, 0.7),
                             font: asset_server.load("fonts/FiraSans-Bold.ttf"),
                         },
                     ),
@@ -64,7 +317,8 @@ impl Menu {
                     align_self: AlignSelf::Center,
                     ..default()
                 }),
-            );
+            )
+            .insert(Visibility { is_visible: false });
         commands
             .spawn()
             .insert(Menu)
@@ -74,7 +328,6 @@ impl Menu {
                     translation: Vec3::new(-186.0, -290.0, 0.0),
                     scale: Vec3::new(1.3, 1.3, 1.0),
                     rotation: Quat::from_rotation_z(-0.2),
-                    ..default()
                 },
                 sprite: Sprite {
                     custom_size: Some(Vec2::new(300.0, 200.0)),
@@ -93,7 +346,6 @@ impl Menu {
                     translation: Vec3::new(188.0, 270.0, 0.0),
                     scale: Vec3::new(1.0, 1.0, 1.0),
                     rotation: Quat::from_rotation_z(0.0f32.to_radians()),
-                    ..default()
                 },
                 sprite: Sprite {
                     custom_size: Some(Vec2::new(300.0, 200.0)),
@@ -112,7 +364,6 @@ impl Menu {
                     translation: Vec3::new(-304.0, 294.0, 0.0),
                     scale: Vec3::new(0.7, 0.7, 1.0),
                     rotation: Quat::from_rotation_z(0.2),
-                    ..default()
                 },
                 sprite: Sprite {
                     custom_size: Some(Vec2::new(150.0, 150.0)),
@@ -121,19 +372,105 @@ impl Menu {
                 texture: asset_server.load("images/unsafe_ferris_2.png"),
                 ..default()
             });
+
+        commands.insert_resource(seed_entry);
+        commands.insert_resource(cursor);
     } 
     
 
     fn update(
         mut commands: Commands,
         keyboard_input: Res<Input<KeyCode>>,
+        actions: Res<ActionState>,
+        controls: Res<Controls>,
         mut global: ResMut<Global>,
+        run_assets: RunAssets,
+        mut screens: MenuScreens,
+        mut seed_entry: ResMut<SeedEntry>,
+        mut cursor: ResMut<MenuCursor>,
+        last_replay: Option<Res<LastReplay>>,
     ) {
-        let play = keyboard_input.just_pressed(KeyCode::Space);
+        let mut picked = None;
+        if seed_entry.typing {
+            Menu::update_seed_entry(&keyboard_input, &actions, &mut seed_entry);
+        } else if screens.showing_table() {
+            // the hall of fame covers the menu until it is closed again
+            if actions.just_pressed(Action::Back) || actions.just_pressed(Action::Confirm) {
+                screens.toggle_table();
+            }
+        } else if actions.just_pressed(Action::Up) {
+            cursor.0 = (cursor.0 + MenuItem::ALL.len() - 1) % MenuItem::ALL.len();
+        } else if actions.just_pressed(Action::Down) {
+            cursor.0 = (cursor.0 + 1) % MenuItem::ALL.len();
+        } else if actions.just_pressed(Action::Confirm) {
+            match MenuItem::ALL[cursor.0] {
+                MenuItem::HallOfFame => screens.toggle_table(),
+                MenuItem::Controls => open_controls_screen(&mut commands, &run_assets.asset_server, &controls),
+                MenuItem::Difficulty => global.toggle_difficulty(),
+                MenuItem::Players => global.toggle_players(),
+                MenuItem::Seed => seed_entry.typing = true,
+                item => picked = Some(item),
+            }
+        }
+
+        for mut text in screens.text_query.iter_mut() {
+            Menu::refresh_items(&mut text, &cursor, &global, &seed_entry, &controls);
+        }
+
+        if !run_assets.is_loaded() {
+            return;
+        }
+
+        let endless = match picked {
+            Some(MenuItem::WatchLastRun) => {
+                let replay = match last_replay {
+                    Some(last_replay) => Some(last_replay.0.clone()),
+                    None => Replay::load(),
+                };
+                match replay {
+                    Some(replay) => start_replay(&mut commands, &mut global, replay),
+                    None => warn!("There is no replay to watch yet"),
+                }
+                return;
+            }
+            Some(MenuItem::Play) => false,
+            Some(MenuItem::Endless) => true,
+            _ => return,
+        };
+
+        // a seed that doesn't fit can't be confirmed, so it never gets here
+        let seed = match seed_entry.seed() {
+            Some(seed) => seed,
+            None => return,
+        };
+        commands.insert_resource(GameRng::new(seed));
+
+        if endless {
+            global.start_playing(GameMode::Endless { seed });
+        } else {
+            global.start_playing(GameMode::Campaign);
+        }
+        commands.insert_resource(NextState(GameState::LoadWaveState));
+    }
+
+    /*
+      Digits and backspace edit the seed, Confirm takes it once it fits in a u64 and Back goes back to a random one
+    */
+    fn update_seed_entry(keyboard_input: &Input<KeyCode>, actions: &ActionState, seed_entry: &mut SeedEntry) {
+        for (key, digit) in DIGIT_KEYS.iter() {
+            if keyboard_input.just_pressed(*key) && seed_entry.digits.len() < MAX_SEED_DIGITS {
+                seed_entry.digits.push(*digit);
+            }
+        }
+        if keyboard_input.just_pressed(KeyCode::Back) {
+            seed_entry.digits.pop();
+        }
 
-        if play {
-            global.start_playing();
-            commands.insert_resource(NextState(GameState::LoadWaveState));
+        if actions.just_pressed(Action::Confirm) && seed_entry.is_valid() {
+            seed_entry.typing = false;
+        } else if actions.just_pressed(Action::Back) {
+            seed_entry.digits.clear();
+            seed_entry.typing = false;
         }
     }
 
@@ -144,6 +481,8 @@ impl Menu {
         for menu_entity in query.iter() {
             commands.entity(menu_entity).despawn();
         }
+        commands.remove_resource::<SeedEntry>();
+        commands.remove_resource::<MenuCursor>();
     }
 }
 
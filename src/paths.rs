@@ -0,0 +1,337 @@
+use bevy::prelude::*;
+use iyes_loopless::prelude::*;
+use rand::Rng;
+use std::f32::consts::TAU;
+
+use crate::{
+    patterns::angle_towards,
+    shared::*,
+    simulation::{SimulationApp, SimulationPhase},
+    waves::{Entry, Wave},
+    GameState,
+};
+
+/*
+  A curve from its first point to its last, t runs from 0 to 1
+*/
+#[derive(Clone, Debug, PartialEq)]
+pub enum Curve {
+    // starts at the first point and ends at the last, the points between pull the curve towards them
+    Bezier(Vec<Vec2>),
+    // passes through every point
+    CatmullRom(Vec<Vec2>),
+    // a straight line from start to end, weaving waves times across it
+    Sine { start: Vec2, end: Vec2, amplitude: f32, waves: f32 },
+}
+
+impl Curve {
+    pub fn point(&self, t: f32) -> Vec2 {
+        let t = t.clamp(0.0, 1.0);
+
+        match self {
+            Curve::Bezier(points) => {
+                // de Casteljau, works for any number of points
+                let mut points = points.clone();
+                while points.len() > 1 {
+                    points = points.windows(2).map(|pair| pair[0].lerp(pair[1], t)).collect();
+                }
+                points.first().copied().unwrap_or(Vec2::ZERO)
+            }
+            Curve::CatmullRom(points) => {
+                if points.len() < 2 {
+                    return points.first().copied().unwrap_or(Vec2::ZERO);
+                }
+
+                let segments = points.len() - 1;
+                let scaled = t * segments as f32;
+                let segment = (scaled as usize).min(segments - 1);
+                let local = scaled - segment as f32;
+
+                // the ends are repeated so the curve reaches them
+                let point = |index: isize| points[index.clamp(0, segments as isize) as usize];
+                let segment = segment as isize;
+                let (p0, p1, p2, p3) = (point(segment - 1), point(segment), point(segment + 1), point(segment + 2));
+
+                let local2 = local * local;
+                let local3 = local2 * local;
+                0.5 * (p1 * 2.0
+                    + (p2 - p0) * local
+                    + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * local2
+                    + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * local3)
+            }
+            Curve::Sine { start, end, amplitude, waves } => {
+                let across = (*end - *start).perp().normalize_or_zero();
+                start.lerp(*end, t) + across * *amplitude * (t * waves * TAU).sin()
+            }
+        }
+    }
+
+    pub fn start(&self) -> Vec2 {
+        self.point(0.0)
+    }
+
+    pub fn end(&self) -> Vec2 {
+        self.point(1.0)
+    }
+
+    /*
+      The direction of travel at t, not normalised
+    */
+    pub fn tangent(&self, t: f32) -> Vec2 {
+        const EPSILON: f32 = 0.001;
+        self.point(t + EPSILON) - self.point(t - EPSILON)
+    }
+}
+
+/*
+  Moves an entity along a curve at an even speed over duration seconds after waiting delay seconds at its start,
+  turned to face the way it is going. Removed once the entity arrives.
+*/
+#[derive(Component)]
+pub struct PathFollower {
+    curve: Curve,
+    // the length of the curve up to each of the SAMPLES evenly spaced values of t
+    lengths: Vec<f32>,
+    delay: f32,
+    duration: f32,
+    elapsed: f32,
+}
+
+impl PathFollower {
+    const SAMPLES: usize = 64;
+
+    pub fn new(curve: Curve, delay: f32, duration: f32) -> PathFollower {
+        let mut lengths = vec![0.0];
+        let mut previous = curve.start();
+        for sample in 1..=PathFollower::SAMPLES {
+            let point = curve.point(sample as f32 / PathFollower::SAMPLES as f32);
+            lengths.push(lengths[sample - 1] + previous.distance(point));
+            previous = point;
+        }
+
+        PathFollower {
+            curve,
+            lengths,
+            delay,
+            duration,
+            elapsed: 0.0,
+        }
+    }
+
+    /*
+      Takes as long as the curve needs at speed
+    */
+    pub fn at_speed(curve: Curve, delay: f32, speed: f32) -> PathFollower {
+        let mut follower = PathFollower::new(curve, delay, 0.0);
+        follower.duration = follower.lengths[PathFollower::SAMPLES] / speed.max(1.0);
+        follower
+    }
+
+    pub fn start(&self) -> Vec2 {
+        self.curve.start()
+    }
+
+    pub fn end(&self) -> Vec2 {
+        self.curve.end()
+    }
+
+    /*
+      Until it arrives, counting the delay
+    */
+    pub fn seconds(&self) -> f32 {
+        self.delay + self.duration
+    }
+
+    /*
+      The t that is share of the way along the curve
+    */
+    fn t(&self, share: f32) -> f32 {
+        let total = self.lengths[PathFollower::SAMPLES];
+        if total <= 0.0 {
+            return 1.0;
+        }
+
+        let distance = share.clamp(0.0, 1.0) * total;
+        let sample = self.lengths.partition_point(|length| *length < distance).clamp(1, PathFollower::SAMPLES);
+        let (before, after) = (self.lengths[sample - 1], self.lengths[sample]);
+        let within = if after > before { (distance - before) / (after - before) } else { 0.0 };
+
+        (sample as f32 - 1.0 + within) / PathFollower::SAMPLES as f32
+    }
+}
+
+impl Entry {
+    // how far below its slot a swoop comes up from
+    const SWOOP_RISE: f32 = 150.0;
+    const WEAVE_AMPLITUDE: f32 = 60.0;
+    const WEAVE_WAVES: f32 = 2.0;
+    const LOOP_CENTRE: Vec2 = Vec2::new(0.0, -50.0);
+    const LOOP_RADIUS: f32 = 150.0;
+
+    /*
+      The way into slot for an alien in this choreography
+    */
+    pub fn curve(&self, slot: Vec2, rng: &mut impl Rng) -> Curve {
+        match self {
+            Entry::Scatter => {
+                let start = Wave::get_starting_location(rng);
+                // bends to one side or the other of the straight line in
+                let bend = (slot - start).perp().normalize_or_zero() * (rng.gen::<f32>() - 0.5) * WINDOW_WIDTH / 2.0;
+
+                Curve::Bezier(vec![start, start.lerp(slot, 0.5) + bend, slot])
+            }
+            Entry::SwoopLeft | Entry::SwoopRight => {
+                let side = if *self == Entry::SwoopLeft { -1.0 } else { 1.0 };
+
+                Curve::CatmullRom(vec![
+                    Vec2::new(side * (RIGHT_WALL + 60.0), 100.0),
+                    Vec2::new(side * 100.0, -150.0),
+                    Vec2::new(-side * 250.0, -50.0),
+                    slot - Vec2::new(0.0, Entry::SWOOP_RISE),
+                    slot,
+                ])
+            }
+            Entry::Weave => Curve::Sine {
+                start: Vec2::new(slot.x, TOP_WALL + 60.0),
+                end: slot,
+                amplitude: Entry::WEAVE_AMPLITUDE,
+                waves: Entry::WEAVE_WAVES,
+            },
+            Entry::Loop => {
+                let around = |angle: f32| {
+                    Entry::LOOP_CENTRE + Vec2::new(angle.to_radians().sin(), angle.to_radians().cos()) * Entry::LOOP_RADIUS
+                };
+
+                Curve::CatmullRom(vec![
+                    Vec2::new(0.0, TOP_WALL + 60.0),
+                    around(0.0),
+                    around(90.0),
+                    around(180.0),
+                    around(270.0),
+                    around(0.0),
+                    slot,
+                ])
+            }
+        }
+    }
+}
+
+pub struct PathsPlugin;
+
+impl Plugin for PathsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_simulation_system(SimulationPhase::Movement, follow_paths.run_in_state(GameState::LoadWaveState))
+            .add_simulation_system(SimulationPhase::Movement, follow_paths.run_in_state(GameState::Playing));
+    }
+}
+
+fn follow_paths(
+    mut commands: Commands,
+    mut query: Query<(Entity, &mut PathFollower, &mut Transform)>,
+) {
+    for (entity, mut follower, mut transform) in query.iter_mut() {
+        if follower.delay > 0.0 {
+            follower.delay -= TIME_STEP;
+            continue;
+        }
+
+        follower.elapsed += TIME_STEP;
+        let share = if follower.duration > 0.0 { follower.elapsed / follower.duration } else { 1.0 };
+        let t = follower.t(share);
+
+        transform.translation = follower.curve.point(t).extend(transform.translation.z);
+
+        if share < 1.0 {
+            // alien sprites look down
+            transform.rotation = Quat::from_rotation_z(angle_towards(follower.curve.tangent(t)).to_radians());
+        } else {
+            transform.rotation = Quat::IDENTITY;
+            commands.entity(entity).remove::<PathFollower>();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn curves() -> Vec<Curve> {
+        let points = vec![
+            Vec2::new(-100.0, 300.0),
+            Vec2::new(50.0, -20.0),
+            Vec2::new(200.0, 100.0),
+            Vec2::new(0.0, 0.0),
+        ];
+
+        vec![
+            Curve::Bezier(points.clone()),
+            Curve::CatmullRom(points),
+            Curve::Sine {
+                start: Vec2::new(0.0, 500.0),
+                end: Vec2::new(100.0, 100.0),
+                amplitude: 60.0,
+                waves: 2.0,
+            },
+        ]
+    }
+
+    fn assert_near(a: Vec2, b: Vec2) {
+        assert!(a.distance(b) < 0.01, "{} isn't {}", a, b);
+    }
+
+    #[test]
+    fn curves_run_from_start_to_end() {
+        for curve in curves() {
+            let (start, end) = match &curve {
+                Curve::Bezier(points) | Curve::CatmullRom(points) => (points[0], points[points.len() - 1]),
+                Curve::Sine { start, end, .. } => (*start, *end),
+            };
+
+            assert_near(curve.point(0.0), start);
+            assert_near(curve.point(1.0), end);
+            // t outside 0 to 1 stays at the ends
+            assert_near(curve.point(-1.0), start);
+            assert_near(curve.point(2.0), end);
+        }
+    }
+
+    #[test]
+    fn follower_t_covers_the_curve_in_order() {
+        for curve in curves() {
+            let follower = PathFollower::new(curve, 0.0, 1.0);
+
+            assert_eq!(follower.t(0.0), 0.0);
+            assert!((follower.t(1.0) - 1.0).abs() < 0.0001);
+
+            let shares: Vec<f32> = (0..=20).map(|step| follower.t(step as f32 / 20.0)).collect();
+            assert!(shares.windows(2).all(|pair| pair[0] <= pair[1]), "{:?}", shares);
+        }
+    }
+
+    #[test]
+    fn follower_waits_out_its_delay() {
+        let curve = Curve::Bezier(vec![Vec2::new(0.0, 0.0), Vec2::new(100.0, 0.0)]);
+        let start = curve.start().extend(0.0);
+        // ten steps waiting, then ten moving, half a step to spare either way
+        let follower = PathFollower::new(curve, 9.5 * TIME_STEP, 9.5 * TIME_STEP);
+
+        let mut world = World::new();
+        let entity = world.spawn().insert(follower).insert(Transform::from_translation(start)).id();
+        let mut stage = SystemStage::single(follow_paths);
+
+        for _ in 0..10 {
+            stage.run(&mut world);
+        }
+        assert_near(world.get::<Transform>(entity).unwrap().translation.truncate(), Vec2::ZERO);
+        assert!(world.get::<PathFollower>(entity).is_some());
+
+        stage.run(&mut world);
+        assert!(world.get::<Transform>(entity).unwrap().translation.x > 0.0);
+
+        for _ in 0..10 {
+            stage.run(&mut world);
+        }
+        assert_near(world.get::<Transform>(entity).unwrap().translation.truncate(), Vec2::new(100.0, 0.0));
+        assert!(world.get::<PathFollower>(entity).is_none());
+    }
+}
@@ -0,0 +1,386 @@
+use bevy::{prelude::*, utils::HashSet};
+use iyes_loopless::prelude::*;
+use rand::Rng;
+use serde::Deserialize;
+use std::{f32::consts::FRAC_PI_2, time::Duration};
+
+use crate::{
+    archetypes::Shooting,
+    player::Ship,
+    rng::GameRng,
+    shared::*,
+    simulation::{SimulationApp, SimulationPhase},
+    weapons::Piercing,
+    GameState,
+};
+
+pub const BULLET_FLASH_SIZE: Vec2 = Vec2::new(35.0, 35.0);
+pub const BULLET_FLASH_DURATION_IN_SECONDS: f32 = 0.1;
+
+/*
+  The shape of one shot. Angles are in degrees from straight down, positive angles lean right.
+  The bullets, their size and speed come from the Shooting the pattern belongs to.
+*/
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub enum BulletPattern {
+    // one bullet straight down, from a random side of the shooter
+    Single,
+    // count bullets fanned evenly over spread degrees
+    Fan { count: u32, spread: f32 },
+    // count bullets evenly around a full circle
+    Ring { count: u32 },
+    // count bullets one after another, interval seconds apart, each turned by turn degrees from the last
+    Spiral { count: u32, turn: f32, interval: f32 },
+    // a fan centred on the nearest ship, straight down when there is none
+    Aimed { count: u32, spread: f32 },
+    // like Aimed, but centred on where the ship will be when the bullets get there if it keeps moving
+    Lead { count: u32, spread: f32 },
+    // an Aimed fan of Seekers, they turn by at most turn_rate degrees a second and last lifetime seconds
+    Homing { count: u32, spread: f32, turn_rate: f32, lifetime: f32 },
+    // fires pattern shots times, delay seconds apart
+    Burst { pattern: Box<BulletPattern>, shots: u32, delay: f32 },
+}
+
+/*
+  Where a pattern is fired from
+*/
+pub struct Muzzle {
+    pub shooter: Entity,
+    pub position: Vec2,
+    pub size: Vec2,
+    // from the shooter's centre, bullets and the flash start here
+    pub offset: Vec2,
+    // the nearest ship, for the patterns that aim
+    pub target: Option<Target>,
+}
+
+#[derive(Clone, Copy)]
+pub struct Target {
+    pub position: Vec2,
+    pub velocity: Vec2,
+}
+
+/*
+  A slow alien bullet that steers toward the nearest ship until its lifetime runs out, ship bullets can shoot it down
+*/
+#[derive(Component)]
+pub struct Seeker {
+    turn_rate: f32,
+    lifetime: Timer,
+}
+
+/*
+  The rest of a Spiral or Burst, fired from the shooter's position at the time
+*/
+#[derive(Component, Default)]
+pub struct PendingShots(Vec<PendingShot>);
+
+struct PendingShot {
+    in_seconds: f32,
+    shooting: Shooting,
+    pattern: BulletPattern,
+    offset: Vec2,
+    turn: f32,
+}
+
+impl BulletPattern {
+    /*
+      Spawns the bullets for one shot, turned by turn degrees, and queues whatever comes later in pending
+    */
+    pub fn fire(
+        &self,
+        commands: &mut Commands,
+        shooting: &Shooting,
+        muzzle: &Muzzle,
+        turn: f32,
+        pending: &mut PendingShots,
+        rng: &mut impl Rng,
+    ) {
+        let (angles, offset) = match self {
+            BulletPattern::Single => {
+                let side = if rng.gen::<f32>() < 0.5 { 1.0 } else { -1.0 };
+                (vec![turn], muzzle.offset + Vec2::new(side * muzzle.size.x / 2., 0.))
+            }
+            BulletPattern::Fan { count, spread } => (fan(*count, *spread, turn), muzzle.offset),
+            BulletPattern::Ring { count } => {
+                let count = (*count).max(1);
+                ((0..count).map(|i| turn + 360.0 * i as f32 / count as f32).collect(), muzzle.offset)
+            }
+            BulletPattern::Spiral { count, turn: step, interval } => {
+                for i in 1..*count {
+                    pending.0.push(PendingShot {
+                        in_seconds: interval * i as f32,
+                        shooting: shooting.clone(),
+                        pattern: BulletPattern::Fan { count: 1, spread: 0.0 },
+                        offset: muzzle.offset,
+                        turn: turn + step * i as f32,
+                    });
+                }
+                (vec![turn], muzzle.offset)
+            }
+            BulletPattern::Aimed { count, spread } | BulletPattern::Homing { count, spread, .. } => {
+                let origin = muzzle.position + muzzle.offset;
+                let aim = muzzle.target.map_or(0.0, |target| angle_towards(target.position - origin));
+                (fan(*count, *spread, aim + turn), muzzle.offset)
+            }
+            BulletPattern::Lead { count, spread } => {
+                let origin = muzzle.position + muzzle.offset;
+                let aim = muzzle
+                    .target
+                    .map_or(0.0, |target| angle_towards(intercept(origin, &target, shooting.speed) - origin));
+                (fan(*count, *spread, aim + turn), muzzle.offset)
+            }
+            BulletPattern::Burst { pattern, shots, delay } => {
+                for i in 1..*shots {
+                    pending.0.push(PendingShot {
+                        in_seconds: delay * i as f32,
+                        shooting: shooting.clone(),
+                        pattern: (**pattern).clone(),
+                        offset: muzzle.offset,
+                        turn,
+                    });
+                }
+                return pattern.fire(commands, shooting, muzzle, turn, pending, rng);
+            }
+        };
+
+        let origin = muzzle.position + offset;
+        for angle in angles {
+            let mut bullet = commands.spawn();
+            bullet.insert_bundle(BulletBundle::new(
+                origin,
+                shooting.bullet.clone(),
+                shooting.size,
+                Velocity(direction(angle) * shooting.speed),
+                angle,
+                Bullet::Alien,
+            ));
+
+            if let BulletPattern::Homing { turn_rate, lifetime, .. } = self {
+                bullet.insert(Seeker {
+                    turn_rate: *turn_rate,
+                    lifetime: Timer::from_seconds(*lifetime, false),
+                });
+            }
+        }
+
+        let bullet_flash = commands
+            .spawn()
+            .insert_bundle(SpriteBundle {
+                transform: Transform {
+                    translation: offset.extend(1.0),
+                    ..default()
+                },
+                sprite: Sprite {
+                    custom_size: Some(BULLET_FLASH_SIZE),
+                    ..default()
+                },
+                texture: shooting.flash.clone(),
+                ..default()
+            })
+            .insert(DespawnTimer::from_seconds(BULLET_FLASH_DURATION_IN_SECONDS))
+            .id();
+
+        commands.entity(muzzle.shooter).add_child(bullet_flash);
+    }
+}
+
+fn fan(count: u32, spread: f32, centre: f32) -> Vec<f32> {
+    match count {
+        0 => Vec::new(),
+        1 => vec![centre],
+        _ => (0..count)
+            .map(|i| centre - spread / 2.0 + spread * i as f32 / (count - 1) as f32)
+            .collect(),
+    }
+}
+
+/*
+  The unit vector for an angle in degrees from straight down
+*/
+pub fn direction(angle: f32) -> Vec2 {
+    Vec2::new(angle.to_radians().sin(), -angle.to_radians().cos())
+}
+
+/*
+  The angle in degrees from straight down of a vector, the inverse of direction
+*/
+pub fn angle_towards(vector: Vec2) -> f32 {
+    vector.x.atan2(-vector.y).to_degrees()
+}
+
+/*
+  Where a bullet fired from origin at speed meets target if the target keeps its velocity,
+  the target's current position when the bullet can never catch it
+*/
+fn intercept(origin: Vec2, target: &Target, speed: f32) -> Vec2 {
+    // solves |to_target + velocity * t| = speed * t for the soonest t > 0
+    let to_target = target.position - origin;
+    let a = target.velocity.length_squared() - speed * speed;
+    let b = 2.0 * to_target.dot(target.velocity);
+    let c = to_target.length_squared();
+
+    let times = if a.abs() < f32::EPSILON {
+        vec![-c / b]
+    } else {
+        let discriminant = b * b - 4.0 * a * c;
+        if discriminant < 0.0 {
+            Vec::new()
+        } else {
+            let root = discriminant.sqrt();
+            vec![(-b - root) / (2.0 * a), (-b + root) / (2.0 * a)]
+        }
+    };
+
+    times
+        .into_iter()
+        .filter(|time| time.is_finite() && *time > 0.0)
+        .min_by(|a, b| a.total_cmp(b))
+        .map_or(target.position, |time| target.position + target.velocity * time)
+}
+
+/*
+  The ship closest to position that is still up
+*/
+pub fn nearest_ship<'a>(
+    ships: impl Iterator<Item = (&'a Transform, &'a Velocity, &'a Health)>,
+    position: Vec2,
+) -> Option<Target> {
+    ships
+        .filter(|(_, _, health)| health.0 > 0)
+        .map(|(transform, velocity, _)| Target {
+            position: transform.translation.truncate(),
+            velocity: velocity.0,
+        })
+        .min_by(|a, b| a.position.distance_squared(position).total_cmp(&b.position.distance_squared(position)))
+}
+
+pub struct PatternsPlugin;
+
+impl Plugin for PatternsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_simulation_system(SimulationPhase::Ai, fire_pending_shots.run_in_state(GameState::Playing))
+            .add_simulation_system(SimulationPhase::Ai, steer_seekers.run_in_state(GameState::Playing))
+            .add_simulation_system(SimulationPhase::Damage, check_for_seeker_collisions.run_in_state(GameState::Playing));
+    }
+}
+
+fn fire_pending_shots(
+    mut commands: Commands,
+    mut shooter_query: Query<(Entity, &Transform, &Collider, &mut PendingShots), Without<Ship>>,
+    ship_query: Query<(&Transform, &Velocity, &Health), With<Ship>>,
+    mut rng: ResMut<GameRng>,
+) {
+    for (shooter, transform, collider, mut pending) in shooter_query.iter_mut() {
+        for shot in pending.0.iter_mut() {
+            shot.in_seconds -= TIME_STEP;
+        }
+
+        let (due, waiting): (Vec<PendingShot>, Vec<PendingShot>) =
+            pending.0.drain(..).partition(|shot| shot.in_seconds <= 0.0);
+        pending.0 = waiting;
+
+        let position = transform.translation.truncate();
+        for shot in due {
+            let muzzle = Muzzle {
+                shooter,
+                position,
+                size: collider.size,
+                offset: shot.offset,
+                target: nearest_ship(ship_query.iter(), position + shot.offset),
+            };
+            shot.pattern.fire(&mut commands, &shot.shooting, &muzzle, shot.turn, &mut pending, rng.gameplay());
+        }
+    }
+}
+
+fn steer_seekers(
+    mut commands: Commands,
+    mut seeker_query: Query<(Entity, &mut Transform, &mut Velocity, &mut Seeker), Without<Ship>>,
+    ship_query: Query<(&Transform, &Velocity, &Health), With<Ship>>,
+) {
+    for (seeker_entity, mut transform, mut velocity, mut seeker) in seeker_query.iter_mut() {
+        seeker.lifetime.tick(Duration::from_secs_f32(TIME_STEP));
+        if seeker.lifetime.finished() {
+            commands.entity(seeker_entity).despawn_recursive();
+            continue;
+        }
+
+        let position = transform.translation.truncate();
+        let target = match nearest_ship(ship_query.iter(), position) {
+            Some(target) => target,
+            None => continue,
+        };
+
+        velocity.0 = turn_towards(velocity.0, target.position - position, seeker.turn_rate.to_radians() * TIME_STEP);
+        // alien bullet sprites point down
+        transform.rotation = Quat::from_rotation_z(velocity.y.atan2(velocity.x) + FRAC_PI_2);
+    }
+}
+
+fn check_for_seeker_collisions(
+    mut commands: Commands,
+    mut hits: EventReader<BulletHitSeeker>,
+    seeker_query: Query<&Transform, With<Seeker>>,
+    bullet_query: Query<Option<&Piercing>, With<Bullet>>,
+    animations: Res<Animations>,
+) {
+    // a seeker can be hit by more than one bullet in the same step
+    let mut destroyed = HashSet::new();
+
+    for hit in hits.iter() {
+        if destroyed.contains(&hit.bullet) || destroyed.contains(&hit.seeker) {
+            continue;
+        }
+        let (transform, piercing) = match (seeker_query.get(hit.seeker), bullet_query.get(hit.bullet)) {
+            (Ok(transform), Ok(piercing)) => (transform, piercing),
+            _ => continue,
+        };
+
+        // piercing bullets keep going
+        if piercing.is_none() {
+            destroyed.insert(hit.bullet);
+            commands.entity(hit.bullet).despawn_recursive();
+        }
+        destroyed.insert(hit.seeker);
+        commands.entity(hit.seeker).despawn_recursive();
+
+        spawn_explosion(&mut commands, &animations, transform.translation, 0.5);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(position: Vec2, velocity: Vec2) -> Target {
+        Target { position, velocity }
+    }
+
+    #[test]
+    fn intercept_a_stationary_target() {
+        let position = Vec2::new(100.0, -300.0);
+
+        assert_eq!(intercept(Vec2::ZERO, &target(position, Vec2::ZERO), 300.0), position);
+    }
+
+    #[test]
+    fn intercept_a_moving_target() {
+        let ship = target(Vec2::new(0.0, -400.0), Vec2::new(200.0, 0.0));
+        let meeting = intercept(Vec2::ZERO, &ship, 400.0);
+
+        // the bullet and the ship get there at the same time
+        let bullet_time = meeting.length() / 400.0;
+        let ship_time = (meeting - ship.position).length() / 200.0;
+        assert!((bullet_time - ship_time).abs() < 0.001, "{} and {}", bullet_time, ship_time);
+        assert!(meeting.x > 0.0);
+    }
+
+    #[test]
+    fn intercept_falls_back_when_the_target_is_too_fast() {
+        // running straight away faster than the bullet flies
+        let ship = target(Vec2::new(0.0, -400.0), Vec2::new(0.0, -500.0));
+
+        assert_eq!(intercept(Vec2::ZERO, &ship, 300.0), ship.position);
+    }
+}
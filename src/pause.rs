@@ -0,0 +1,268 @@
+use bevy::{audio::AudioSink, prelude::*};
+use bevy_tweening::{Animator, AnimatorState};
+use iyes_loopless::prelude::*;
+
+use crate::{
+    aliens::Alien,
+    controls::{Action, ActionState, Controls},
+    shared::reset_game,
+    GameState, Global, WaveIntro, WaveMusic,
+};
+
+const PAUSE_BACKDROP_COLOR: Color = Color::rgba(0.0, 0.0, 0.0, 0.6);
+
+/*
+  Present while a run is paused and until the first frame after it resumes.
+  The enter and exit systems of Playing and LoadWaveState don't run while it exists,
+  so pausing and resuming doesn't respawn or tear down the wave.
+*/
+pub struct Paused {
+    previous: GameState,
+}
+
+/*
+  The run was abandoned from the pause menu (restart or quit to menu)
+*/
+struct AbandonRun;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum PauseView {
+    Main,
+    Settings,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum PauseItem {
+    Resume,
+    Restart,
+    Settings,
+    Quit,
+    Difficulty,
+    Back,
+}
+
+impl PauseView {
+    fn items(&self) -> &'static [PauseItem] {
+        match self {
+            PauseView::Main => &[PauseItem::Resume, PauseItem::Restart, PauseItem::Settings, PauseItem::Quit],
+            PauseView::Settings => &[PauseItem::Difficulty, PauseItem::Back],
+        }
+    }
+}
+
+/*
+  The item of the current view Confirm picks, back at the top whenever the view changes
+*/
+struct PauseCursor(usize);
+
+#[derive(Component)]
+struct PauseMenu;
+
+pub struct PausePlugin;
+
+impl Plugin for PausePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(pause.run_in_state(GameState::Playing))
+            .add_system(pause.run_in_state(GameState::LoadWaveState))
+            .add_system(update_pause_menu.run_in_state(GameState::Paused))
+            .add_system(
+                clear_paused
+                    .run_not_in_state(GameState::Paused)
+                    .run_if_resource_exists::<Paused>(),
+            )
+            .add_enter_system(GameState::Paused, freeze_run)
+            .add_enter_system(GameState::Paused, spawn_pause_menu)
+            .add_exit_system(GameState::Paused, unfreeze_run)
+            .add_exit_system(GameState::Paused, despawn_pause_menu)
+            .add_exit_system(GameState::Paused, reset_game.run_if_resource_exists::<AbandonRun>())
+            .add_exit_system(GameState::Paused, abandon_run.run_if_resource_exists::<AbandonRun>());
+    }
+}
+
+fn pause(mut commands: Commands, actions: Res<ActionState>, state: Res<CurrentState<GameState>>) {
+    if actions.just_pressed(Action::Pause) {
+        commands.insert_resource(Paused { previous: state.0.clone() });
+        commands.insert_resource(NextState(GameState::Paused));
+    }
+}
+
+fn clear_paused(mut commands: Commands) {
+    commands.remove_resource::<Paused>();
+}
+
+fn freeze_run(
+    mut animator_query: Query<&mut Animator<Transform>>,
+    audio_sinks: Res<Assets<AudioSink>>,
+    wave_music: Option<Res<WaveMusic>>,
+) {
+    // the tweens moving incoming and wandering aliens run outside the fixed timestep
+    for mut animator in animator_query.iter_mut() {
+        animator.state = AnimatorState::Paused;
+    }
+
+    if let Some(sink) = wave_music.and_then(|music| audio_sinks.get(&music.sink)) {
+        sink.pause();
+    }
+}
+
+fn unfreeze_run(
+    mut animator_query: Query<&mut Animator<Transform>>,
+    audio_sinks: Res<Assets<AudioSink>>,
+    wave_music: Option<Res<WaveMusic>>,
+) {
+    for mut animator in animator_query.iter_mut() {
+        animator.state = AnimatorState::Playing;
+    }
+
+    if let Some(sink) = wave_music.and_then(|music| audio_sinks.get(&music.sink)) {
+        sink.play();
+    }
+}
+
+fn menu_text(view: PauseView, cursor: &PauseCursor, global: &Global, controls: &Controls) -> String {
+    let mut text = String::new();
+    for (index, item) in view.items().iter().enumerate() {
+        let label = match item {
+            PauseItem::Resume => format!("[{}] Resume", controls.describe(Action::Pause)),
+            PauseItem::Restart => "Restart".to_string(),
+            PauseItem::Settings => "Settings".to_string(),
+            PauseItem::Quit => format!("[{}] Quit to Menu", controls.describe(Action::Back)),
+            PauseItem::Difficulty => format!("Difficulty: {}", global.difficulty().name()),
+            PauseItem::Back => format!("[{}] Back", controls.describe(Action::Back)),
+        };
+        let marker = if index == cursor.0 { "→ " } else { "   " };
+        text.push_str(&format!("{}{}\n", marker, label));
+    }
+
+    text.push_str(&format!(
+        "\n[{}] [{}] Select  [{}] Choose",
+        controls.describe(Action::Up),
+        controls.describe(Action::Down),
+        controls.describe(Action::Confirm),
+    ));
+    text
+}
+
+fn spawn_pause_menu(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    global: Res<Global>,
+    controls: Res<Controls>,
+) {
+    commands.insert_resource(PauseView::Main);
+    commands.insert_resource(PauseCursor(0));
+
+    commands
+        .spawn()
+        .insert(PauseMenu)
+        .insert(Name::new("Pause Menu"))
+        .insert_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            color: PAUSE_BACKDROP_COLOR.into(),
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle::from_sections([
+                TextSection::new(
+                    "PAUSED\n\n",
+                    TextStyle {
+                        font_size: 60.0,
+                        color: Color::rgb(1.0, 1.0, 1.0),
+                        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    },
+                ),
+                TextSection::new(
+                    menu_text(PauseView::Main, &PauseCursor(0), &global, &controls),
+                    TextStyle {
+                        font_size: 30.0,
+                        color: Color::rgb(0.7, 0.7, 0.7),
+                        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    },
+                ),
+            ]));
+        });
+}
+
+fn update_pause_menu(
+    mut commands: Commands,
+    actions: Res<ActionState>,
+    controls: Res<Controls>,
+    paused: Res<Paused>,
+    mut view: ResMut<PauseView>,
+    mut cursor: ResMut<PauseCursor>,
+    mut global: ResMut<Global>,
+    mut text_query: Query<&mut Text>,
+    menu_query: Query<&Children, With<PauseMenu>>,
+) {
+    let items = view.items();
+    let picked = if actions.just_pressed(Action::Confirm) { Some(items[cursor.0]) } else { None };
+
+    if actions.just_pressed(Action::Up) {
+        cursor.0 = (cursor.0 + items.len() - 1) % items.len();
+    } else if actions.just_pressed(Action::Down) {
+        cursor.0 = (cursor.0 + 1) % items.len();
+    }
+
+    match *view {
+        PauseView::Main => {
+            if actions.just_pressed(Action::Pause) || picked == Some(PauseItem::Resume) {
+                commands.insert_resource(NextState(paused.previous.clone()));
+            } else if picked == Some(PauseItem::Restart) {
+                commands.remove_resource::<Paused>();
+                commands.insert_resource(AbandonRun);
+                commands.insert_resource(NextState(GameState::LoadWaveState));
+            } else if actions.just_pressed(Action::Back) || picked == Some(PauseItem::Quit) {
+                commands.remove_resource::<Paused>();
+                commands.insert_resource(AbandonRun);
+                commands.insert_resource(NextState(GameState::Menu));
+            } else if picked == Some(PauseItem::Settings) {
+                *view = PauseView::Settings;
+                cursor.0 = 0;
+            }
+        }
+        PauseView::Settings => {
+            if picked == Some(PauseItem::Difficulty) {
+                global.toggle_difficulty();
+            } else if actions.just_pressed(Action::Back) || actions.just_pressed(Action::Pause) || picked == Some(PauseItem::Back) {
+                *view = PauseView::Main;
+                cursor.0 = 0;
+            }
+        }
+    }
+
+    for children in menu_query.iter() {
+        for child in children.iter() {
+            if let Ok(mut text) = text_query.get_mut(*child) {
+                text.sections[1].value = menu_text(*view, &cursor, &global, &controls);
+            }
+        }
+    }
+}
+
+fn despawn_pause_menu(mut commands: Commands, query: Query<Entity, With<PauseMenu>>) {
+    for menu in query.iter() {
+        commands.entity(menu).despawn_recursive();
+    }
+    commands.remove_resource::<PauseView>();
+    commands.remove_resource::<PauseCursor>();
+}
+
+/*
+  reset_game clears the ship and the hud, this clears what the paused wave left behind
+*/
+fn abandon_run(
+    mut commands: Commands,
+    alien_query: Query<Entity, With<Alien>>,
+    intro_query: Query<Entity, With<WaveIntro>>,
+) {
+    for entity in alien_query.iter().chain(intro_query.iter()) {
+        commands.entity(entity).despawn_recursive();
+    }
+    commands.remove_resource::<AbandonRun>();
+}
@@ -1,5 +1,16 @@
-use crate::{shared::*, GameState};
-use bevy::{prelude::*, sprite::collide_aabb::collide};
+use crate::{
+    aliens::{Alien, Bounty},
+    boss::DevilFerris,
+    controls::{Action, ActionState},
+    divers::Diving,
+    powerups::{PowerUp, PowerUpEffects},
+    rng::GameRng,
+    shared::*,
+    simulation::{SimulationApp, SimulationPhase},
+    weapons::{Arsenal, Weapon, Weapons},
+    GameState, Global, Paused,
+};
+use bevy::{prelude::*, utils::HashSet};
 use iyes_loopless::prelude::*;
 use std::time::Duration;
 
@@ -8,7 +19,6 @@ const SHIP_COLLISION_SIZE: Vec2 = Vec2::new(110., 70.);
 const GAP_BETWEEN_SHIP_AND_FLOOR: f32 = 5.0;
 const SHIP_SPEED: f32 = 450.;
 const SHOOTING_COOLDOWN_IN_SECONDS: f32 = 1.2;
-pub const SHIP_BULLET_SIZE: Vec2 = Vec2::new(33.0, 70.0);
 pub const SHIP_BULLET_FLASH_SIZE: Vec2 = Vec2::new(33.0, 70.0);
 pub const INITIAL_HEALTH_POINTS: u32 = 8;
 pub const SHIP_WALK_FRAME_DURATION_IN_MILLIS: u64 = 200;
@@ -18,6 +28,36 @@ pub const HIT_MARKER_DURATION: f32 = 0.75;
 const HEART_SIZE: Vec2 = Vec2::new(30., 30.);
 const HEART_CORNER_OFFSET: Vec2 = Vec2::new(25., 25.);
 const HEART_PADDING_RIGHT: f32 = 10.0;
+// the keys that pick a weapon, for each player
+const WEAPON_KEYS: [[KeyCode; 9]; MAX_PLAYERS] = [
+    [
+        KeyCode::Key1,
+        KeyCode::Key2,
+        KeyCode::Key3,
+        KeyCode::Key4,
+        KeyCode::Key5,
+        KeyCode::Key6,
+        KeyCode::Key7,
+        KeyCode::Key8,
+        KeyCode::Key9,
+    ],
+    [
+        KeyCode::Numpad1,
+        KeyCode::Numpad2,
+        KeyCode::Numpad3,
+        KeyCode::Numpad4,
+        KeyCode::Numpad5,
+        KeyCode::Numpad6,
+        KeyCode::Numpad7,
+        KeyCode::Numpad8,
+        KeyCode::Numpad9,
+    ],
+];
+// every player has their own set of ship actions
+pub const MAX_PLAYERS: usize = Action::SHIPS.len();
+// ships after the first are tinted so the players can tell them apart
+const SHIP_TINTS: [Color; MAX_PLAYERS] = [Color::WHITE, Color::rgb(0.55, 0.8, 1.0)];
+const SHIP_SPACING: f32 = 200.0;
 
 #[derive(Component, PartialEq)]
 enum Torch {
@@ -28,97 +68,275 @@ enum Torch {
 #[derive(Component)]
 pub struct Ship;
 
+/*
+  Which player a ship belongs to, 0 for the first
+*/
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Player(pub usize);
+
+impl Player {
+    pub fn tint(&self) -> Color {
+        SHIP_TINTS[self.0]
+    }
+
+    pub fn label(&self) -> String {
+        format!("P{}", self.0 + 1)
+    }
+}
+
 #[derive(Component, PartialEq, Eq)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum FerrisState {
     WALKING,
     IDLE,
     DEAD,
 }
 
+/*
+  One of the hearts in a player's row, visible while the ship has at least points health
+*/
 #[derive(Component)]
-pub struct HealthDisplayHeart(u32);
+pub struct HealthDisplayHeart {
+    player: Player,
+    points: u32,
+}
+
+/*
+  What a player asked their ship to do this frame, packed into a byte so replays can store it.
+  The low bits are the move and fire actions, the high nibble is the weapon key pressed (1-9, 0 for none).
+*/
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ShipInput(u8);
+
+impl ShipInput {
+    const MOVE_LEFT: u8 = 1 << 0;
+    const MOVE_RIGHT: u8 = 1 << 1;
+    const SHOOT_LEFT: u8 = 1 << 2;
+    const SHOOT_RIGHT: u8 = 1 << 3;
+    const WEAPON_SHIFT: u8 = 4;
+
+    pub fn from_bits(bits: u8) -> ShipInput {
+        ShipInput(bits)
+    }
+
+    /*
+      Weapons are picked with the number keys (the numpad for the second player), they aren't remappable actions
+    */
+    pub fn from_actions(actions: &ActionState, keyboard_input: &Input<KeyCode>, player: usize) -> ShipInput {
+        let [move_left, move_right, fire_left, fire_right] = Action::SHIPS[player];
+
+        let mut bits = 0;
+        for (action, bit) in [
+            (move_left, ShipInput::MOVE_LEFT),
+            (move_right, ShipInput::MOVE_RIGHT),
+            (fire_left, ShipInput::SHOOT_LEFT),
+            (fire_right, ShipInput::SHOOT_RIGHT),
+        ] {
+            if actions.pressed(action) {
+                bits |= bit;
+            }
+        }
+        if let Some(index) = WEAPON_KEYS[player].iter().position(|key| keyboard_input.just_pressed(*key)) {
+            bits |= (index as u8 + 1) << ShipInput::WEAPON_SHIFT;
+        }
+
+        ShipInput(bits)
+    }
+
+    pub fn bits(&self) -> u8 {
+        self.0
+    }
+
+    pub fn move_left(&self) -> bool {
+        self.0 & ShipInput::MOVE_LEFT != 0
+    }
+
+    pub fn move_right(&self) -> bool {
+        self.0 & ShipInput::MOVE_RIGHT != 0
+    }
+
+    pub fn shoot_left(&self) -> bool {
+        self.0 & ShipInput::SHOOT_LEFT != 0
+    }
+
+    pub fn shoot_right(&self) -> bool {
+        self.0 & ShipInput::SHOOT_RIGHT != 0
+    }
+
+    /*
+      The index of the weapon the player switched to this frame
+    */
+    pub fn weapon(&self) -> Option<usize> {
+        match self.0 >> ShipInput::WEAPON_SHIFT {
+            0 => None,
+            key => Some(key as usize - 1),
+        }
+    }
+}
+
+/*
+  The input of every player's ship this frame, indexed by Player
+*/
+#[derive(Default, Clone, Debug, PartialEq, Eq)]
+pub struct ShipInputs(pub Vec<ShipInput>);
+
+impl ShipInputs {
+    pub fn from_actions(actions: &ActionState, keyboard_input: &Input<KeyCode>, players: usize) -> ShipInputs {
+        ShipInputs(
+            (0..players)
+                .map(|player| ShipInput::from_actions(actions, keyboard_input, player))
+                .collect(),
+        )
+    }
+
+    pub fn get(&self, player: Player) -> ShipInput {
+        self.0.get(player.0).copied().unwrap_or_default()
+    }
+}
 
 pub struct PlayerPlugin;
 
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
-        let mut fixedupdate = SystemStage::parallel();
-        fixedupdate.add_system(update_ship.run_in_state(GameState::Playing));
-        fixedupdate.add_system(check_for_ship_collisions.run_in_state(GameState::Playing));
-        fixedupdate.add_system(update_health_display.run_in_state(GameState::Playing));
-        fixedupdate.add_system(update_ferris_display.run_in_state(GameState::Playing));
-
-        app.add_stage_before(
-            CoreStage::Update,
-            "Player_FixedUpdate",
-            FixedTimestepStage::from_stage(Duration::from_secs_f32(TIME_STEP), fixedupdate),
-        )
-        .add_startup_system(load_assets_and_animations)
-        .add_enter_system(GameState::Playing, spawn_ship_health_display)
-        .add_enter_system(GameState::LoadWaveState, spawn_player);
+        app.add_simulation_system(SimulationPhase::Input, update_ship.run_in_state(GameState::Playing))
+            .add_simulation_system(SimulationPhase::Damage, check_for_ship_collisions.run_in_state(GameState::Playing))
+            .add_simulation_system(SimulationPhase::Damage, check_for_alien_rams.run_in_state(GameState::Playing))
+            .add_simulation_system(SimulationPhase::Cleanup, update_health_display.run_in_state(GameState::Playing))
+            .add_simulation_system(SimulationPhase::Cleanup, update_ferris_display.run_in_state(GameState::Playing))
+            .init_resource::<ShipInputs>()
+            .add_startup_system(load_assets_and_animations)
+            .add_enter_system(GameState::Playing, spawn_ship_health_display.run_unless_resource_exists::<Paused>())
+            .add_enter_system(GameState::LoadWaveState, spawn_player.run_unless_resource_exists::<Paused>());
     }
 }
 
 fn check_for_ship_collisions(
     mut commands: Commands,
-    mut ship_query: Query<(Entity, &Transform, &mut Health, &Collider, &mut FerrisState), With<Ship>>,
+    mut hits: EventReader<BulletHitShip>,
+    mut ship_query: Query<(&Transform, &mut Health, &mut FerrisState, &PowerUpEffects), With<Ship>>,
     sprites: Res<Sprites>,
-    bullet_query: Query<(Entity, &Transform, &Bullet, &Collider)>,
+    bullet_query: Query<With<Bullet>>,
 ) {
-    let (ship_entity, ship_transform, mut health, ship_collider, mut ferris_state) = ship_query.single_mut();
+    // each ship takes at most one hit per step
+    let mut ships_hit = HashSet::new();
 
-    let min_bullet_hit_height = ship_transform.translation.y;
+    for hit in hits.iter() {
+        if ships_hit.contains(&hit.ship) {
+            continue;
+        }
 
-    for (bullet_entity, bullet_transform, bullet, bullet_collider) in bullet_query.iter() {
-        if bullet == &Bullet::Ship {
-            // ignore bullets from the ship
+        let (ship_transform, mut health, mut ferris_state, effects) = match ship_query.get_mut(hit.ship) {
+            Ok(ship) => ship,
+            Err(_) => continue,
+        };
+
+        // bullets pass over a ship that is already down
+        if health.0 == 0 {
             continue;
         }
 
-        let bullet_translation = bullet_transform.translation;
+        let bullet_translation = hit.position;
 
-        if bullet_translation.y < min_bullet_hit_height {
+        if bullet_translation.y < ship_transform.translation.y || bullet_query.get(hit.bullet).is_err() {
             continue;
         }
 
-        if collide(
-            ship_transform.translation,
-            ship_collider.size,
-            bullet_translation,
-            bullet_collider.size,
-        ).is_some() {
-            commands.entity(bullet_entity).despawn();
-
-            let hit_marker = commands
-                    .spawn()
-                    .insert_bundle(SpriteBundle {
-                        transform: Transform {
-                            translation: Vec3::new(
-                                bullet_translation.x - ship_transform.translation.x,
-                                bullet_translation.y - ship_transform.translation.y - 20.0,
-                                1.0
-                            ),
-                            ..default()
-                        },
-                        texture: sprites.get("HIT_MARKER"),
-                        sprite: Sprite {
-                            custom_size: Some(HIT_MARKER_SIZE),
-                            ..default()
-                        },
-                        ..default()
-                    })
-                    .insert(DespawnTimer::from_seconds(HIT_MARKER_DURATION))
-                    .id();
-            
-            commands.entity(ship_entity).add_child(hit_marker);
+        commands.entity(hit.bullet).despawn();
 
-            health.0 -= 1;
+        if effects.is_active(PowerUp::Shield) {
+            continue;
+        }
 
-            if health.0 == 0 {
-                *ferris_state = FerrisState::DEAD
-            }
-            break;
+        damage_ship(&mut commands, &sprites, hit.ship, ship_transform, &mut health, &mut ferris_state, bullet_translation);
+        ships_hit.insert(hit.ship);
+    }
+}
+
+/*
+  A diving alien that flies into a ship crashes, it takes a hit off the ship (unless shielded) and scores nothing.
+  The boss never dives, it only goes down to bullets.
+*/
+fn check_for_alien_rams(
+    mut commands: Commands,
+    mut rams: EventReader<AlienHitShip>,
+    mut destroyed_events: EventWriter<AlienDestroyed>,
+    mut ship_query: Query<(&Transform, &mut Health, &mut FerrisState, &PowerUpEffects), With<Ship>>,
+    alien_query: Query<(&Transform, &Bounty), (With<Alien>, With<Diving>, Without<DevilFerris>, Without<Ship>)>,
+    sprites: Res<Sprites>,
+    animations: Res<Animations>,
+) {
+    // an alien can touch both ships in the same step
+    let mut crashed = HashSet::new();
+
+    for ram in rams.iter() {
+        if crashed.contains(&ram.alien) {
+            continue;
         }
+
+        let (ship_transform, mut health, mut ferris_state, effects) = match ship_query.get_mut(ram.ship) {
+            Ok(ship) => ship,
+            Err(_) => continue,
+        };
+        let (alien_transform, bounty) = match alien_query.get(ram.alien) {
+            Ok(alien) => alien,
+            Err(_) => continue,
+        };
+
+        // aliens pass over a ship that is already down
+        if health.0 == 0 {
+            continue;
+        }
+
+        crashed.insert(ram.alien);
+        commands.entity(ram.alien).despawn_recursive();
+        spawn_explosion(&mut commands, &animations, alien_transform.translation, 1.0);
+        destroyed_events.send(AlienDestroyed {
+            position: alien_transform.translation,
+            drop_chance: bounty.drop_chance,
+        });
+
+        if !effects.is_active(PowerUp::Shield) {
+            damage_ship(&mut commands, &sprites, ram.ship, ship_transform, &mut health, &mut ferris_state, ram.position);
+        }
+    }
+}
+
+fn damage_ship(
+    commands: &mut Commands,
+    sprites: &Sprites,
+    ship: Entity,
+    ship_transform: &Transform,
+    health: &mut Health,
+    ferris_state: &mut FerrisState,
+    position: Vec3,
+) {
+    let hit_marker = commands
+            .spawn()
+            .insert_bundle(SpriteBundle {
+                transform: Transform {
+                    translation: Vec3::new(
+                        position.x - ship_transform.translation.x,
+                        position.y - ship_transform.translation.y - 20.0,
+                        1.0
+                    ),
+                    ..default()
+                },
+                texture: sprites.get("HIT_MARKER"),
+                sprite: Sprite {
+                    custom_size: Some(HIT_MARKER_SIZE),
+                    ..default()
+                },
+                ..default()
+            })
+            .insert(DespawnTimer::from_seconds(HIT_MARKER_DURATION))
+            .id();
+
+    commands.entity(ship).add_child(hit_marker);
+
+    health.0 = health.0.saturating_sub(1);
+
+    if health.0 == 0 {
+        *ferris_state = FerrisState::DEAD
     }
 }
 
@@ -144,83 +362,118 @@ fn load_assets_and_animations(
 }
 
 fn get_left_claw_offset() -> Vec2 {
-    Vec2::new(-1.0 * SHIP_SIZE.x / 2.0 + 10.0, 15.0)
+    Vec2::new(-SHIP_SIZE.x / 2.0 + 10.0, 15.0)
 }
 
 fn get_right_claw_offset() -> Vec2 {
-    Vec2::new(1.0 * SHIP_SIZE.x / 2.0 - 10.0, 15.0)
+    Vec2::new(SHIP_SIZE.x / 2.0 - 10.0, 15.0)
 }
 
-fn spawn_ship_health_display(mut commands: Commands, sprites: ResMut<Sprites>) {
-    let first_heart_offset = Vec2::new(
-        LEFT_WALL + HEART_CORNER_OFFSET.x,
-        TOP_WALL - HEART_CORNER_OFFSET.y,
-    );
+/*
+  A row of hearts per ship, the first player's in the top left corner and the second's in the top right,
+  any further players get the rows below
+*/
+fn spawn_ship_health_display(
+    mut commands: Commands,
+    sprites: ResMut<Sprites>,
+    ship_query: Query<&Player, With<Ship>>,
+    hearts_query: Query<&HealthDisplayHeart>,
+) {
     let heart_horizontal_gap = HEART_PADDING_RIGHT + HEART_SIZE.x;
+    let heart_vertical_gap = HEART_PADDING_RIGHT + HEART_SIZE.y;
 
-    for i in 0..INITIAL_HEALTH_POINTS {
-        let heart_x = first_heart_offset.x + heart_horizontal_gap * i as f32;
-        let heart_y = first_heart_offset.y - HEART_SIZE.y;
+    for player in ship_query.iter() {
+        if hearts_query.iter().any(|heart| heart.player == *player) {
+            // hearts persist between waves
+            continue;
+        }
 
-        commands
-            .spawn()
-            .insert(HealthDisplayHeart(i + 1))
-            .insert_bundle(SpriteBundle {
-                transform: Transform {
-                    translation: Vec3::new(heart_x, heart_y, 0.0),
-                    scale: Vec3::splat(1.0),
-                    ..default()
-                },
-                sprite: Sprite {
-                    custom_size: Some(HEART_SIZE),
+        let (first_heart_x, direction) = match player.0 % 2 {
+            0 => (LEFT_WALL + HEART_CORNER_OFFSET.x, 1.0),
+            _ => (RIGHT_WALL - HEART_CORNER_OFFSET.x, -1.0),
+        };
+        let heart_y = TOP_WALL - HEART_CORNER_OFFSET.y - HEART_SIZE.y - heart_vertical_gap * (player.0 / 2) as f32;
+
+        for i in 0..INITIAL_HEALTH_POINTS {
+            let heart_x = first_heart_x + direction * heart_horizontal_gap * i as f32;
+
+            commands
+                .spawn()
+                .insert(HealthDisplayHeart {
+                    player: *player,
+                    points: i + 1,
+                })
+                .insert_bundle(SpriteBundle {
+                    transform: Transform {
+                        translation: Vec3::new(heart_x, heart_y, 0.0),
+                        scale: Vec3::splat(1.0),
+                        ..default()
+                    },
+                    sprite: Sprite {
+                        custom_size: Some(HEART_SIZE),
+                        color: player.tint(),
+                        ..default()
+                    },
+                    texture: sprites.get("HEART"),
                     ..default()
-                },
-                texture: sprites.get("HEART"),
-                ..default()
-            })
-            .insert(Name::new("Health Display Heart"));
+                })
+                .insert(Name::new("Health Display Heart"));
+        }
     }
 }
 
 fn update_health_display(
-    mut commands: Commands,
-    ship_query: Query<&Health, With<Ship>>,
-    hearts_query: Query<(Entity, &HealthDisplayHeart)>,
+    ship_query: Query<(&Player, &Health), With<Ship>>,
+    mut hearts_query: Query<(&mut Visibility, &HealthDisplayHeart)>,
 ) {
-    let health = ship_query.single();
-
-    for (heart_entity, display_heart) in hearts_query.iter() {
-        if display_heart.0 > health.0 {
-            commands.entity(heart_entity).despawn();
+    for (player, health) in ship_query.iter() {
+        for (mut visibility, display_heart) in hearts_query.iter_mut() {
+            if display_heart.player == *player {
+                visibility.is_visible = display_heart.points <= health.0;
+            }
         }
     }
 }
 
+/*
+  Spawns a ship for every player that doesn't have one yet, side by side along the floor
+*/
 fn spawn_player(
     mut commands: Commands,
     sprites: Res<Sprites>,
     animations: Res<Animations>,
-    ship_query: Query<Entity, With<Ship>>,
+    global: Res<Global>,
+    ship_query: Query<&Player, With<Ship>>,
 ) {
-    if ship_query.get_single().is_ok() {
-        // ship has already been spawned
-        return;
-    }
-
-    // ship
+    let players = global.players();
     let ship_y = BOTTOM_WALL + GAP_BETWEEN_SHIP_AND_FLOOR + SHIP_SIZE.y / 2.;
 
+    for index in 0..players {
+        let player = Player(index);
+        if ship_query.iter().any(|spawned| *spawned == player) {
+            // ships persist between waves
+            continue;
+        }
+
+        let ship_x = (index as f32 - (players - 1) as f32 / 2.0) * SHIP_SPACING;
+        spawn_ship(&mut commands, &sprites, &animations, player, Vec2::new(ship_x, ship_y));
+    }
+}
+
+fn spawn_ship(commands: &mut Commands, sprites: &Sprites, animations: &Animations, player: Player, position: Vec2) {
     let ferris = commands
         .spawn()
         .insert(Ship)
+        .insert(player)
         .insert(Health(INITIAL_HEALTH_POINTS))
         .insert_bundle(SpriteBundle {
             transform: Transform {
-                translation: Vec3::new(0.0, ship_y, 0.0),
+                translation: position.extend(0.0),
                 ..default()
             },
             sprite: Sprite {
                 custom_size: Some(SHIP_SIZE),
+                color: player.tint(),
                 ..default()
             },
             texture: sprites.get("HAPPY_FERRIS"),
@@ -230,8 +483,11 @@ fn spawn_player(
         .insert(AnimationState::default())
         .insert(FerrisState::IDLE)
         .insert(Collider { size: SHIP_COLLISION_SIZE})
+        .insert(Velocity(Vec2::ZERO))
+        .insert(PowerUpEffects::default())
+        .insert(Name::new(format!("Ship {}", player.label())))
         .id();
-    
+
     let left_torch = commands
         .spawn()
         .insert(ShootingCooldown::new_finished(DurationType::Fixed(Fixed(SHOOTING_COOLDOWN_IN_SECONDS))))
@@ -248,6 +504,7 @@ fn spawn_player(
             ..default()
         })
         .insert(Torch::Left)
+        .insert(Weapon::default())
         .insert(Visibility { is_visible: true })
         .id(); 
 
@@ -267,6 +524,7 @@ fn spawn_player(
             ..default()
         })
         .insert(Torch::Right)
+        .insert(Weapon::default())
         .insert(Visibility { is_visible: true })
         .id();  
     
@@ -275,68 +533,97 @@ fn spawn_player(
 }
 
 fn update_ship(
-    keyboard_input: Res<Input<KeyCode>>,
+    ship_inputs: Res<ShipInputs>,
     mut ship_query: Query<
         (
+            Entity,
+            &Player,
             &mut Transform,
             &Children,
             &mut FerrisState,
             &Collider,
+            &PowerUpEffects,
+            &mut Velocity,
         ),
         With<Ship>,
     >,
-    mut torch_query: Query<(&Transform, &mut Visibility, &mut ShootingCooldown, &Torch), Without<Ship>>,
-    sprites: Res<Sprites>,
+    mut torch_query: Query<(&mut Transform, &mut Visibility, &mut ShootingCooldown, &Torch, &mut Weapon), Without<Ship>>,
+    weapons: Res<Weapons>,
+    arsenals: Res<Assets<Arsenal>>,
+    mut rng: ResMut<GameRng>,
     mut commands: Commands,
 ) {
-    let (mut transform, children, mut state, collider) = ship_query.single_mut();
-
-    if *state == FerrisState::DEAD {
-        return;
-    }
-
-    let mut direction = 0.;
-
-    let move_left = keyboard_input.pressed(KeyCode::A);
-    let move_right = keyboard_input.pressed(KeyCode::D);
-    let shoot_left = keyboard_input.pressed(KeyCode::J);
-    let shoot_right = keyboard_input.pressed(KeyCode::K);
-
-    if move_left {
-        direction = -1.;
-        *state = FerrisState::WALKING;
-    } else if move_right {
-        direction = 1.;
-        *state = FerrisState::WALKING;
-    } else {
-        *state = FerrisState::IDLE;
-    }
-
-    transform.translation.x += direction * SHIP_SPEED * TIME_STEP;
-
-    transform.translation.x = transform.translation.x.clamp(
-        LEFT_WALL + collider.size.x / 2.0 + WALL_THICKNESS,
-        RIGHT_WALL - collider.size.x / 2.0 - WALL_THICKNESS,
-    );
-
-    // update torchs (only show if you can fire)
-    for child in children {
-        if let Ok((torch_transform, mut torch_visibility, mut torch_cooldown, torch)) = torch_query.get_mut(*child) {
-            torch_visibility.is_visible = torch_cooldown.finished();
-
-            if torch_cooldown.finished() && (shoot_left && torch == &Torch::Left || shoot_right && torch == &Torch::Right) {
-                torch_cooldown.reset();
-
-                let bullet_offset = torch_transform.translation.truncate(); 
+    for (ship, player, mut transform, children, mut state, collider, effects, mut velocity) in ship_query.iter_mut() {
+        if *state == FerrisState::DEAD {
+            velocity.0 = Vec2::ZERO;
+            continue;
+        }
 
-                let bullet_x = transform.translation.x + bullet_offset.x;
-                let bullet_y = transform.translation.y + bullet_offset.y;
+        let mut direction = 0.;
+
+        let ship_input = ship_inputs.get(*player);
+        let move_left = ship_input.move_left();
+        let move_right = ship_input.move_right();
+        let shoot_left = ship_input.shoot_left();
+        let shoot_right = ship_input.shoot_right();
+
+        if move_left {
+            direction = -1.;
+            *state = FerrisState::WALKING;
+        } else if move_right {
+            direction = 1.;
+            *state = FerrisState::WALKING;
+        } else {
+            *state = FerrisState::IDLE;
+        }
 
-                commands.spawn().insert_bundle(BulletBundle::from_ship(
-                    Vec2::new(bullet_x, bullet_y),
-                    sprites.get("FERRIS_BULLET"),
-                )); 
-            } 
+        let previous_x = transform.translation.x;
+        transform.translation.x += direction * SHIP_SPEED * TIME_STEP;
+
+        transform.translation.x = transform.translation.x.clamp(
+            LEFT_WALL + collider.size.x / 2.0 + WALL_THICKNESS,
+            RIGHT_WALL - collider.size.x / 2.0 - WALL_THICKNESS,
+        );
+        // aliens that lead their shots read this, it stays 0 against a wall
+        velocity.0 = Vec2::new((transform.translation.x - previous_x) / TIME_STEP, 0.0);
+
+        // update torchs (only show if you can fire)
+        for child in children {
+            if let Ok((mut torch_transform, mut torch_visibility, mut torch_cooldown, torch, mut weapon)) = torch_query.get_mut(*child) {
+                torch_visibility.is_visible = torch_cooldown.finished();
+
+                let weapon_type = match weapons.get(weapon.index, &arsenals) {
+                    Some(weapon_type) => weapon_type,
+                    None => continue,
+                };
+                let pressed = match torch {
+                    Torch::Left => shoot_left,
+                    Torch::Right => shoot_right,
+                };
+
+                if torch_cooldown.finished() {
+                    if let Some(multiplier) = weapon.trigger(weapon_type, pressed) {
+                        torch_cooldown.set_duration(DurationType::Fixed(Fixed(
+                            weapon_type.cooldown * effects.cooldown_multiplier(),
+                        )));
+                        torch_cooldown.reset(rng.gameplay());
+
+                        let bullet_offset = torch_transform.translation.truncate(); 
+
+                        let bullet_x = transform.translation.x + bullet_offset.x;
+                        let bullet_y = transform.translation.y + bullet_offset.y;
+
+                        weapon_type.fire(
+                            &mut commands,
+                            ship,
+                            Vec2::new(bullet_x, bullet_y),
+                            multiplier,
+                            effects.is_active(PowerUp::SpreadShot),
+                        );
+                    }
+                }
+                torch_transform.scale = Vec3::splat(weapon.torch_scale());
+            }
         }
     }
 }
@@ -360,18 +647,18 @@ fn update_ferris_display(
         _ => panic!("Image data not found"),
     };
 
-    let (mut animation_state, ferris_animation, mut texture, ferris_state) = query.single_mut();
-
-    match ferris_state {
-        FerrisState::IDLE => {
-            *texture = sprites.get("HAPPY_FERRIS");
-        }
-        FerrisState::WALKING => {
-            animation_state.update(ferris_animation, Duration::from_secs_f32(TIME_STEP));
-            *texture = sprites.get(images[animation_state.frame_index() as usize].as_str());
-        }
-        FerrisState::DEAD => {
-            *texture = sprites.get("ALARMED_FERRIS");
+    for (mut animation_state, ferris_animation, mut texture, ferris_state) in query.iter_mut() {
+        match ferris_state {
+            FerrisState::IDLE => {
+                *texture = sprites.get("HAPPY_FERRIS");
+            }
+            FerrisState::WALKING => {
+                animation_state.update(ferris_animation, Duration::from_secs_f32(TIME_STEP));
+                *texture = sprites.get(images[animation_state.frame_index()].as_str());
+            }
+            FerrisState::DEAD => {
+                *texture = sprites.get("ALARMED_FERRIS");
+            }
         }
     }
 }
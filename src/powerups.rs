@@ -0,0 +1,333 @@
+use bevy::{prelude::*, sprite::collide_aabb::collide, utils::HashMap};
+use iyes_loopless::prelude::*;
+use rand::Rng;
+use std::time::Duration;
+
+use crate::{
+    player::{Player, Ship, INITIAL_HEALTH_POINTS},
+    rng::GameRng,
+    shared::*,
+    simulation::{SimulationApp, SimulationPhase},
+    GameState, Paused,
+};
+
+const POWER_UP_SIZE: Vec2 = Vec2::new(35.0, 35.0);
+const POWER_UP_FALL_SPEED: f32 = 150.0;
+const POWER_UP_LAYER: f32 = 0.8;
+
+const RAPID_FIRE_COOLDOWN_MULTIPLIER: f32 = 1.0 / 3.0;
+const SCORE_MULTIPLIER: u32 = 2;
+const SHIELD_SIZE: Vec2 = Vec2::new(150.0, 150.0);
+const SHIELD_COLOR: Color = Color::rgba(0.4, 0.7, 1.0, 0.5);
+
+const POWER_UP_HUD_FONT_SIZE: f32 = 20.0;
+const POWER_UP_HUD_PADDING_TOP: Val = Val::Px(45.0);
+const POWER_UP_HUD_PADDING_LEFT: Val = Val::Px(345.0); // right of the hearts
+
+#[derive(Component, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum PowerUp {
+    Heal,
+    RapidFire,
+    SpreadShot,
+    Shield,
+    ScoreMultiplier,
+}
+
+impl PowerUp {
+    const ALL: [PowerUp; 5] = [
+        PowerUp::Heal,
+        PowerUp::RapidFire,
+        PowerUp::SpreadShot,
+        PowerUp::Shield,
+        PowerUp::ScoreMultiplier,
+    ];
+
+    fn random(rng: &mut impl Rng) -> PowerUp {
+        PowerUp::ALL[rng.gen_range(0..PowerUp::ALL.len())]
+    }
+
+    fn sprite(&self) -> &'static str {
+        match self {
+            PowerUp::Heal => "POWER_UP_HEAL",
+            PowerUp::RapidFire => "POWER_UP_RAPID_FIRE",
+            PowerUp::SpreadShot => "POWER_UP_SPREAD_SHOT",
+            PowerUp::Shield => "POWER_UP_SHIELD",
+            PowerUp::ScoreMultiplier => "POWER_UP_SCORE_MULTIPLIER",
+        }
+    }
+
+    /*
+      How long the effect lasts, None for instant effects
+    */
+    fn duration(&self) -> Option<f32> {
+        match self {
+            PowerUp::Heal => None,
+            PowerUp::RapidFire => Some(8.0),
+            PowerUp::SpreadShot => Some(8.0),
+            PowerUp::Shield => Some(5.0),
+            PowerUp::ScoreMultiplier => Some(10.0),
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            PowerUp::Heal => "HEAL",
+            PowerUp::RapidFire => "RAPID FIRE",
+            PowerUp::SpreadShot => "SPREAD SHOT",
+            PowerUp::Shield => "SHIELD",
+            PowerUp::ScoreMultiplier => "SCORE x2",
+        }
+    }
+}
+
+/*
+  The timed power-ups currently affecting a ship
+*/
+#[derive(Component, Default)]
+pub struct PowerUpEffects {
+    timers: HashMap<PowerUp, Timer>,
+}
+
+impl PowerUpEffects {
+    pub fn is_active(&self, power_up: PowerUp) -> bool {
+        self.timers.contains_key(&power_up)
+    }
+
+    pub fn score_multiplier(&self) -> u32 {
+        if self.is_active(PowerUp::ScoreMultiplier) { SCORE_MULTIPLIER } else { 1 }
+    }
+
+    /*
+      Scales the cooldown of whichever weapon the ship fires
+    */
+    pub fn cooldown_multiplier(&self) -> f32 {
+        if self.is_active(PowerUp::RapidFire) { RAPID_FIRE_COOLDOWN_MULTIPLIER } else { 1.0 }
+    }
+
+    fn activate(&mut self, power_up: PowerUp, duration: f32) {
+        // picking up an active power-up restarts its timer
+        self.timers.insert(power_up, Timer::from_seconds(duration, false));
+    }
+
+    fn tick(&mut self, delta: Duration) -> Vec<PowerUp> {
+        let mut expired = Vec::new();
+
+        for (power_up, timer) in self.timers.iter_mut() {
+            if timer.tick(delta).finished() {
+                expired.push(*power_up);
+            }
+        }
+        for power_up in expired.iter() {
+            self.timers.remove(power_up);
+        }
+
+        expired
+    }
+}
+
+#[derive(Component)]
+struct ShieldBubble;
+
+#[derive(Component)]
+pub struct PowerUpHud;
+
+pub struct PowerUpsPlugin;
+
+impl Plugin for PowerUpsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_simulation_system(SimulationPhase::Movement, update_power_ups.run_in_state(GameState::Playing))
+            .add_simulation_system(SimulationPhase::Collision, collect_power_ups.run_in_state(GameState::Playing))
+            .add_simulation_system_set(
+                SimulationPhase::Cleanup,
+                ConditionSet::new()
+                    .run_in_state(GameState::Playing)
+                    .with_system(spawn_power_ups)
+                    .with_system(update_power_up_effects)
+                    .with_system(update_power_up_hud)
+                    .into(),
+            )
+            .add_startup_system(load_assets)
+            .add_enter_system(GameState::Playing, spawn_power_up_hud.run_unless_resource_exists::<Paused>());
+    }
+}
+
+fn load_assets(asset_server: Res<AssetServer>, mut sprites: ResMut<Sprites>) {
+    sprites.add("POWER_UP_HEAL", asset_server.load("images/heart.png"));
+    sprites.add("POWER_UP_RAPID_FIRE", asset_server.load("images/ferris_bullet_flash_2.png"));
+    sprites.add("POWER_UP_SPREAD_SHOT", asset_server.load("images/victory_bullets/4.png"));
+    sprites.add("POWER_UP_SHIELD", asset_server.load("images/rust.png"));
+    sprites.add("POWER_UP_SCORE_MULTIPLIER", asset_server.load("images/rust_white.png"));
+}
+
+fn spawn_power_ups(
+    mut commands: Commands,
+    mut destroyed: EventReader<AlienDestroyed>,
+    sprites: Res<Sprites>,
+    mut rng: ResMut<GameRng>,
+) {
+    for alien in destroyed.iter() {
+        if rng.gameplay().gen::<f32>() >= alien.drop_chance {
+            continue;
+        }
+
+        let power_up = PowerUp::random(rng.gameplay());
+
+        commands
+            .spawn()
+            .insert(power_up)
+            .insert(Name::new("Power Up"))
+            .insert(Velocity(Vec2::new(0.0, -POWER_UP_FALL_SPEED)))
+            .insert_bundle(SpriteBundle {
+                transform: Transform {
+                    translation: alien.position.truncate().extend(POWER_UP_LAYER),
+                    ..default()
+                },
+                sprite: Sprite {
+                    custom_size: Some(POWER_UP_SIZE),
+                    ..default()
+                },
+                texture: sprites.get(power_up.sprite()),
+                ..default()
+            });
+    }
+}
+
+fn update_power_ups(mut commands: Commands, mut query: Query<(Entity, &mut Transform, &Velocity), With<PowerUp>>) {
+    for (entity, mut transform, velocity) in query.iter_mut() {
+        transform.translation.x += velocity.x * TIME_STEP;
+        transform.translation.y += velocity.y * TIME_STEP;
+
+        if transform.translation.y < BOTTOM_WALL - POWER_UP_SIZE.y {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+fn collect_power_ups(
+    mut commands: Commands,
+    power_up_query: Query<(Entity, &Transform, &PowerUp)>,
+    mut ship_query: Query<(Entity, &Transform, &Collider, &mut Health, &mut PowerUpEffects), With<Ship>>,
+) {
+    for (ship_entity, ship_transform, ship_collider, mut health, mut effects) in ship_query.iter_mut() {
+        if health.0 == 0 {
+            continue;
+        }
+
+        for (power_up_entity, power_up_transform, power_up) in power_up_query.iter() {
+            if collide(
+                ship_transform.translation,
+                ship_collider.size,
+                power_up_transform.translation,
+                POWER_UP_SIZE,
+            ).is_none() {
+                continue;
+            }
+
+            commands.entity(power_up_entity).despawn();
+
+            match power_up.duration() {
+                Some(duration) => {
+                    if *power_up == PowerUp::Shield && !effects.is_active(PowerUp::Shield) {
+                        let shield = commands
+                            .spawn()
+                            .insert(ShieldBubble)
+                            .insert_bundle(SpriteBundle {
+                                transform: Transform {
+                                    translation: Vec3::new(0.0, 0.0, 2.0),
+                                    ..default()
+                                },
+                                sprite: Sprite {
+                                    custom_size: Some(SHIELD_SIZE),
+                                    color: SHIELD_COLOR,
+                                    ..default()
+                                },
+                                ..default()
+                            })
+                            .id();
+                        commands.entity(ship_entity).add_child(shield);
+                    }
+
+                    effects.activate(*power_up, duration);
+                }
+                None => {
+                    // heal is the only instant power-up
+                    health.0 = (health.0 + 1).min(INITIAL_HEALTH_POINTS);
+                }
+            }
+        }
+    }
+}
+
+fn update_power_up_effects(
+    mut commands: Commands,
+    mut ship_query: Query<(&Children, &mut PowerUpEffects), With<Ship>>,
+    shield_query: Query<Entity, With<ShieldBubble>>,
+) {
+    for (children, mut effects) in ship_query.iter_mut() {
+        for expired in effects.tick(Duration::from_secs_f32(TIME_STEP)) {
+            if expired != PowerUp::Shield {
+                continue;
+            }
+
+            for child in children.iter() {
+                if shield_query.get(*child).is_ok() {
+                    commands.entity(*child).despawn();
+                }
+            }
+        }
+    }
+}
+
+fn spawn_power_up_hud(mut commands: Commands, asset_server: Res<AssetServer>, hud_query: Query<With<PowerUpHud>>) {
+    if !hud_query.is_empty() {
+        return;
+    }
+
+    commands
+        .spawn()
+        .insert(PowerUpHud)
+        .insert(Name::new("Power Up HUD"))
+        .insert_bundle(
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font_size: POWER_UP_HUD_FONT_SIZE,
+                    color: SCORE_COLOR,
+                    font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    top: POWER_UP_HUD_PADDING_TOP,
+                    left: POWER_UP_HUD_PADDING_LEFT,
+                    ..default()
+                },
+                ..default()
+            }),
+        );
+}
+
+fn update_power_up_hud(
+    ship_query: Query<(&Player, &PowerUpEffects), With<Ship>>,
+    mut hud_query: Query<&mut Text, With<PowerUpHud>>,
+) {
+    let mut hud = String::new();
+    let co_op = ship_query.iter().count() > 1;
+
+    for (player, effects) in ship_query.iter() {
+        if co_op && !effects.timers.is_empty() {
+            hud.push_str(&format!("{}: ", player.label()));
+        }
+        for power_up in PowerUp::ALL.iter() {
+            if let Some(timer) = effects.timers.get(power_up) {
+                let remaining = timer.duration().as_secs_f32() - timer.elapsed_secs();
+                hud.push_str(&format!("{} {:.1}s  ", power_up.label(), remaining));
+            }
+        }
+    }
+
+    for mut text in hud_query.iter_mut() {
+        text.sections[0].value = hud.clone();
+    }
+}
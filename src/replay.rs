@@ -0,0 +1,641 @@
+use bevy::{prelude::*, utils::Instant};
+use iyes_loopless::prelude::*;
+use std::{fmt, time::Duration};
+
+use crate::{
+    controls::{ActionState, ActionSystem},
+    pause::Paused,
+    player::{ShipInput, ShipInputs, MAX_PLAYERS},
+    rng::GameRng, shared::TIME_STEP, storage, Difficulty, GameMode, GameState,
+    Global, Scoreboard,
+};
+
+pub const REPLAY_KEY: &str = "last_replay.rwr";
+const REPLAY_HEADER: &str = "rust-wars-replay 2";
+// a frame that took longer than this many steps drops the rest instead of fast-forwarding
+const MAX_STEPS_PER_FRAME: u32 = 5;
+const DIFFICULTIES: [Difficulty; 2] = [Difficulty::Casual, Difficulty::Normal];
+
+/*
+  Hands the simulation stage a whole number of steps every frame.
+  Everything that runs once per frame (tweens, wave checks) then sees the same time as the steps,
+  so repeating the step counts of a run repeats the run.
+*/
+pub struct SimulationClock {
+    accumulator: Duration,
+    last_frame: Option<Instant>,
+    steps: u32,
+    // headless runs step a fixed amount every frame instead of following the wall clock
+    steps_per_frame: Option<u32>,
+}
+
+impl SimulationClock {
+    pub fn real_time() -> SimulationClock {
+        SimulationClock {
+            accumulator: Duration::ZERO,
+            last_frame: None,
+            steps: 0,
+            steps_per_frame: None,
+        }
+    }
+
+    #[cfg(test)]
+    pub fn fixed(steps_per_frame: u32) -> SimulationClock {
+        SimulationClock {
+            steps_per_frame: Some(steps_per_frame),
+            ..SimulationClock::real_time()
+        }
+    }
+
+    pub fn steps(&self) -> u32 {
+        self.steps
+    }
+
+    fn real_steps(&mut self, elapsed: Duration) -> u32 {
+        if let Some(steps) = self.steps_per_frame {
+            return steps;
+        }
+
+        let step = Duration::from_secs_f32(TIME_STEP);
+        self.accumulator += elapsed;
+
+        let mut steps = 0;
+        while self.accumulator >= step && steps < MAX_STEPS_PER_FRAME {
+            self.accumulator -= step;
+            steps += 1;
+        }
+        if self.accumulator >= step {
+            self.accumulator = Duration::ZERO;
+        }
+
+        steps
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct ReplayFrame {
+    steps: u8,
+    // the ShipInput bits of each player, past the replay's player count they stay 0
+    inputs: [u8; MAX_PLAYERS],
+}
+
+/*
+  Everything needed to play a run back: how it started, the fixed steps and ship input of every frame
+  it simulated, the states it went through and how it ended
+*/
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Replay {
+    seed: u64,
+    mode: GameMode,
+    difficulty: Difficulty,
+    players: usize,
+    frames: Vec<ReplayFrame>,
+    transitions: Vec<(u32, GameState)>,
+    score: u32,
+    wave: u32,
+}
+
+#[derive(Debug)]
+pub struct ReplayError(String);
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid replay: {}", self.0)
+    }
+}
+
+impl Replay {
+    fn new(seed: u64, mode: GameMode, difficulty: Difficulty, players: usize) -> Replay {
+        Replay {
+            seed,
+            mode,
+            difficulty,
+            players,
+            frames: Vec::new(),
+            transitions: Vec::new(),
+            score: 0,
+            wave: 0,
+        }
+    }
+
+    pub fn load() -> Option<Replay> {
+        let saved = storage::load(REPLAY_KEY)?;
+
+        match Replay::decode(&saved) {
+            Ok(replay) => Some(replay),
+            Err(error) => {
+                warn!("Failed to load the last replay: {}", error);
+                None
+            }
+        }
+    }
+
+    pub fn save(&self) {
+        if let Err(error) = storage::save(REPLAY_KEY, &self.encode()) {
+            warn!("Failed to save the replay: {}", error);
+        }
+    }
+
+    /*
+      A few lines of text, with runs of identical frames collapsed into `count x steps : inputs`,
+      the inputs being two hex digits per player
+    */
+    pub fn encode(&self) -> String {
+        let mode = match self.mode {
+            GameMode::Campaign => "campaign".to_string(),
+            GameMode::Endless { seed } => format!("endless {}", seed),
+        };
+
+        let mut runs: Vec<(u32, ReplayFrame)> = Vec::new();
+        for frame in self.frames.iter() {
+            match runs.last_mut() {
+                Some((count, last)) if last == frame => *count += 1,
+                _ => runs.push((1, *frame)),
+            }
+        }
+        let frames: Vec<String> = runs
+            .iter()
+            .map(|(count, frame)| {
+                let inputs: String = frame.inputs[..self.players].iter().map(|input| format!("{:02x}", input)).collect();
+                format!("{}x{}:{}", count, frame.steps, inputs)
+            })
+            .collect();
+        let transitions: Vec<String> = self
+            .transitions
+            .iter()
+            .map(|(frame, state)| format!("{}:{}", frame, state_name(state)))
+            .collect();
+
+        format!(
+            "{}\nseed {}\nmode {}\ndifficulty {}\nplayers {}\nresult {} {}\nframes {}\ntransitions {}\n",
+            REPLAY_HEADER,
+            self.seed,
+            mode,
+            self.difficulty.name(),
+            self.players,
+            self.score,
+            self.wave,
+            frames.join(" "),
+            transitions.join(" "),
+        )
+    }
+
+    pub fn decode(encoded: &str) -> Result<Replay, ReplayError> {
+        let mut lines = encoded.lines();
+        if lines.next() != Some(REPLAY_HEADER) {
+            return Err(ReplayError("unknown header".to_string()));
+        }
+
+        let seed = parse_number(field(&mut lines, "seed")?.first().copied(), "seed")?;
+        let mode = match field(&mut lines, "mode")?.as_slice() {
+            ["campaign"] => GameMode::Campaign,
+            ["endless", seed] => GameMode::Endless {
+                seed: parse_number(Some(*seed), "endless seed")?,
+            },
+            _ => return Err(ReplayError("unknown mode".to_string())),
+        };
+        let difficulty = field(&mut lines, "difficulty")?
+            .first()
+            .and_then(|name| DIFFICULTIES.iter().find(|difficulty| difficulty.name() == *name))
+            .copied()
+            .ok_or_else(|| ReplayError("unknown difficulty".to_string()))?;
+        let players: usize = parse_number(field(&mut lines, "players")?.first().copied(), "players")?;
+        if players == 0 || players > MAX_PLAYERS {
+            return Err(ReplayError(format!("unsupported player count {}", players)));
+        }
+        let result = field(&mut lines, "result")?;
+        let score = parse_number(result.first().copied(), "score")?;
+        let wave = parse_number(result.get(1).copied(), "wave")?;
+
+        let mut frames = Vec::new();
+        for run in field(&mut lines, "frames")? {
+            let (count, frame) = run
+                .split_once('x')
+                .ok_or_else(|| ReplayError(format!("bad frame run {}", run)))?;
+            let (steps, input) = frame
+                .split_once(':')
+                .ok_or_else(|| ReplayError(format!("bad frame run {}", run)))?;
+            if input.len() != players * 2 || !input.is_ascii() {
+                return Err(ReplayError(format!("bad input {}", input)));
+            }
+
+            let mut inputs = [0; MAX_PLAYERS];
+            for (player, input) in inputs.iter_mut().zip(input.as_bytes().chunks(2)) {
+                let hex = std::str::from_utf8(input).unwrap_or_default();
+                *player = u8::from_str_radix(hex, 16).map_err(|_| ReplayError(format!("bad input {}", hex)))?;
+            }
+            let frame = ReplayFrame {
+                steps: parse_number(Some(steps), "steps")?,
+                inputs,
+            };
+            let count: usize = parse_number(Some(count), "frame count")?;
+            frames.extend(vec![frame; count]);
+        }
+
+        let mut transitions = Vec::new();
+        for transition in field(&mut lines, "transitions")? {
+            let (frame, state) = transition
+                .split_once(':')
+                .ok_or_else(|| ReplayError(format!("bad transition {}", transition)))?;
+            let state = parse_state(state).ok_or_else(|| ReplayError(format!("unknown state {}", state)))?;
+            transitions.push((parse_number(Some(frame), "transition frame")?, state));
+        }
+
+        Ok(Replay {
+            seed,
+            mode,
+            difficulty,
+            players,
+            frames,
+            transitions,
+            score,
+            wave,
+        })
+    }
+}
+
+fn field<'a>(lines: &mut std::str::Lines<'a>, name: &str) -> Result<Vec<&'a str>, ReplayError> {
+    let line = lines.next().ok_or_else(|| ReplayError(format!("missing {}", name)))?;
+    let mut words = line.split_whitespace();
+    if words.next() != Some(name) {
+        return Err(ReplayError(format!("expected {}", name)));
+    }
+    Ok(words.collect())
+}
+
+fn parse_number<T: std::str::FromStr>(value: Option<&str>, name: &str) -> Result<T, ReplayError> {
+    value
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| ReplayError(format!("bad {}", name)))
+}
+
+fn state_name(state: &GameState) -> &'static str {
+    match state {
+        GameState::Menu => "Menu",
+        GameState::Playing => "Playing",
+        GameState::GameOver => "GameOver",
+        GameState::LoadWaveState => "LoadWaveState",
+        GameState::Victory => "Victory",
+        GameState::Paused => "Paused",
+    }
+}
+
+fn parse_state(name: &str) -> Option<GameState> {
+    [
+        GameState::Menu,
+        GameState::Playing,
+        GameState::GameOver,
+        GameState::LoadWaveState,
+        GameState::Victory,
+        GameState::Paused,
+    ]
+    .into_iter()
+    .find(|state| state_name(state) == name)
+}
+
+/*
+  The states a recording follows. Pausing isn't recorded, paused frames don't simulate anything.
+*/
+fn is_simulated(state: &GameState) -> bool {
+    matches!(state, GameState::LoadWaveState | GameState::Playing)
+}
+
+fn is_finished(state: &GameState) -> bool {
+    matches!(state, GameState::GameOver | GameState::Victory)
+}
+
+/*
+  Present while a live run is being recorded
+*/
+pub struct ReplayRecorder {
+    replay: Replay,
+    last_state: Option<GameState>,
+}
+
+/*
+  The replay of the last run that ended this session, it is also saved under REPLAY_KEY
+*/
+pub struct LastReplay(pub Replay);
+
+/*
+  Present while a replay drives the ship, and on its game over or victory screen
+*/
+pub struct ReplayPlayback {
+    replay: Replay,
+    frame: usize,
+    // whether drive_simulation took this frame from the replay
+    consumed: bool,
+    last_state: Option<GameState>,
+    wave: u32,
+    transitions: usize,
+    finished: bool,
+    diverged: bool,
+}
+
+impl ReplayPlayback {
+    fn new(replay: Replay) -> ReplayPlayback {
+        ReplayPlayback {
+            replay,
+            frame: 0,
+            consumed: false,
+            last_state: None,
+            wave: 0,
+            transitions: 0,
+            finished: false,
+            diverged: false,
+        }
+    }
+
+    fn diverge(&mut self, reason: &str) {
+        if !self.diverged {
+            warn!("Replay diverged at frame {}: {}", self.frame, reason);
+        }
+        self.diverged = true;
+    }
+}
+
+/*
+  Starts a run that plays the replay back, the way the menu starts a live run
+*/
+pub fn start_replay(commands: &mut Commands, global: &mut Global, replay: Replay) {
+    commands.insert_resource(GameRng::new(replay.seed));
+    global.start_playing(replay.mode);
+    global.set_difficulty(replay.difficulty);
+    global.set_players(replay.players);
+    commands.insert_resource(ReplayPlayback::new(replay));
+    commands.insert_resource(NextState(GameState::LoadWaveState));
+}
+
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.world.contains_resource::<SimulationClock>() {
+            app.insert_resource(SimulationClock::real_time());
+        }
+
+        // the state only exists once its transition stage first ran, after this frame's PreUpdate
+        app.add_system_to_stage(
+            CoreStage::PreUpdate,
+            drive_simulation
+                .run_if_resource_exists::<CurrentState<GameState>>()
+                .after(ActionSystem),
+        )
+            .add_system_to_stage(CoreStage::Last, record_frame.run_if_resource_exists::<ReplayRecorder>())
+            .add_system_to_stage(CoreStage::Last, check_playback.run_if_resource_exists::<ReplayPlayback>())
+            .add_enter_system(GameState::LoadWaveState, start_recording.run_unless_resource_exists::<Paused>())
+            .add_enter_system(GameState::Menu, stop_replays);
+    }
+}
+
+/*
+  Decides how many fixed steps this frame runs and what the ships are told to do,
+  from the replay while one plays and from the wall clock and the player's actions otherwise
+*/
+fn drive_simulation(
+    mut time: ResMut<Time>,
+    mut clock: ResMut<SimulationClock>,
+    mut ship_inputs: ResMut<ShipInputs>,
+    actions: Res<ActionState>,
+    global: Res<Global>,
+    keyboard_input: Res<Input<KeyCode>>,
+    playback: Option<ResMut<ReplayPlayback>>,
+    current_state: Res<CurrentState<GameState>>,
+    next_state: Option<Res<NextState<GameState>>>,
+) {
+    // the time system already ran this frame
+    let now = time.last_update().unwrap_or_else(Instant::now);
+    let elapsed = clock.last_frame.map_or(Duration::ZERO, |last| now.duration_since(last));
+    clock.last_frame = Some(now);
+
+    // the state the simulation runs in, after this frame's transition
+    let state = next_state.as_ref().map_or(&current_state.0, |next| &next.0);
+
+    let frame = match playback {
+        Some(mut playback) if is_simulated(state) && !playback.finished => {
+            let frame = playback.replay.frames.get(playback.frame).copied();
+            playback.frame += 1;
+            playback.consumed = true;
+            if frame.is_none() {
+                playback.diverge("the run outlasted the replay");
+            }
+            frame
+        }
+        _ => None,
+    };
+
+    match frame {
+        Some(frame) => {
+            clock.steps = frame.steps as u32;
+            *ship_inputs = ShipInputs(frame.inputs.into_iter().map(ShipInput::from_bits).collect());
+        }
+        None => {
+            clock.steps = clock.real_steps(elapsed);
+            *ship_inputs = ShipInputs::from_actions(&actions, &keyboard_input, global.players());
+        }
+    }
+
+    time.update_with_instant(now + Duration::from_secs_f32(TIME_STEP) * clock.steps);
+}
+
+fn start_recording(
+    mut commands: Commands,
+    global: Res<Global>,
+    rng: Res<GameRng>,
+    playback: Option<Res<ReplayPlayback>>,
+) {
+    if global.current_wave() != 0 {
+        return;
+    }
+    if let Some(playback) = playback {
+        if !playback.finished {
+            return;
+        }
+        commands.remove_resource::<ReplayPlayback>();
+    }
+
+    commands.insert_resource(ReplayRecorder {
+        replay: Replay::new(rng.seed(), global.mode(), global.difficulty(), global.players()),
+        last_state: None,
+    });
+}
+
+fn record_frame(
+    mut commands: Commands,
+    mut recorder: ResMut<ReplayRecorder>,
+    clock: Res<SimulationClock>,
+    ship_inputs: Res<ShipInputs>,
+    state: Res<CurrentState<GameState>>,
+    global: Res<Global>,
+    scoreboard: Option<Res<Scoreboard>>,
+) {
+    let state = &state.0;
+    if !is_simulated(state) && !is_finished(state) {
+        // a run abandoned from the pause menu isn't kept
+        if *state == GameState::Menu {
+            commands.remove_resource::<ReplayRecorder>();
+        }
+        return;
+    }
+
+    let frame = recorder.replay.frames.len() as u32;
+    if recorder.last_state.as_ref() != Some(state) {
+        recorder.replay.transitions.push((frame, state.clone()));
+        recorder.last_state = Some(state.clone());
+    }
+
+    if is_finished(state) {
+        let mut replay = recorder.replay.clone();
+        replay.score = scoreboard.map_or(0, |scoreboard| scoreboard.score);
+        // tests keep their replays to themselves
+        if !cfg!(test) {
+            replay.save();
+        }
+        commands.insert_resource(LastReplay(replay));
+        commands.remove_resource::<ReplayRecorder>();
+        return;
+    }
+
+    let mut inputs = [0; MAX_PLAYERS];
+    for (bits, input) in inputs.iter_mut().zip(ship_inputs.0.iter()) {
+        *bits = input.bits();
+    }
+
+    recorder.replay.wave = global.current_wave();
+    recorder.replay.frames.push(ReplayFrame {
+        steps: clock.steps() as u8,
+        inputs,
+    });
+}
+
+/*
+  Follows the played back run the way record_frame follows a live one and reports where they part
+*/
+fn check_playback(
+    mut playback: ResMut<ReplayPlayback>,
+    state: Res<CurrentState<GameState>>,
+    global: Res<Global>,
+    scoreboard: Option<Res<Scoreboard>>,
+) {
+    let state = &state.0;
+    let consumed = playback.consumed;
+    playback.consumed = false;
+
+    if playback.finished || (!is_simulated(state) && !is_finished(state)) {
+        return;
+    }
+
+    // the frames simulated before this one, which is what record_frame numbers transitions by
+    let mut frame = playback.frame;
+    if consumed {
+        frame -= 1;
+        if !is_simulated(state) {
+            // the state moved on past the one it was taken for, record_frame skipped it as well
+            playback.frame -= 1;
+        }
+    }
+
+    if playback.last_state.as_ref() != Some(state) {
+        match playback.replay.transitions.get(playback.transitions).cloned() {
+            Some(expected) if expected == (frame as u32, state.clone()) => {}
+            expected => {
+                let reason = format!("expected {:?}, reached {:?} at frame {}", expected, state, frame);
+                playback.diverge(&reason);
+            }
+        }
+        playback.transitions += 1;
+        playback.last_state = Some(state.clone());
+    }
+
+    if is_simulated(state) {
+        playback.wave = global.current_wave();
+        return;
+    }
+
+    let score = scoreboard.map_or(0, |scoreboard| scoreboard.score);
+    if score != playback.replay.score {
+        let reason = format!("finished with {} points instead of {}", score, playback.replay.score);
+        playback.diverge(&reason);
+    }
+    if playback.wave != playback.replay.wave {
+        let reason = format!("finished on wave {} instead of {}", playback.wave, playback.replay.wave);
+        playback.diverge(&reason);
+    }
+    playback.finished = true;
+}
+
+fn stop_replays(mut commands: Commands) {
+    commands.remove_resource::<ReplayRecorder>();
+    commands.remove_resource::<ReplayPlayback>();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headless;
+
+    const SEED: u64 = 1234;
+    // long enough for the aliens to invade even an idle ship
+    const MAX_FRAMES: u32 = 60 * 60 * 10;
+
+    fn run_until_finished(app: &mut App, mut input: impl FnMut(&mut App, u32)) {
+        for frame in 0..MAX_FRAMES {
+            input(app, frame);
+            app.update();
+
+            if is_finished(&headless::state(app)) {
+                return;
+            }
+        }
+        panic!("the run didn't finish within {} frames", MAX_FRAMES);
+    }
+
+    #[test]
+    fn encoding_round_trips() {
+        let mut replay = Replay::new(SEED, GameMode::Endless { seed: SEED }, Difficulty::Casual, 2);
+        replay.frames = vec![
+            ReplayFrame { steps: 1, inputs: [0, 0] },
+            ReplayFrame { steps: 1, inputs: [0, 0] },
+            ReplayFrame { steps: 2, inputs: [0x1c, 0x03] },
+        ];
+        replay.transitions = vec![(0, GameState::LoadWaveState), (2, GameState::Playing), (3, GameState::GameOver)];
+        replay.score = 420;
+        replay.wave = 1;
+
+        assert_eq!(Replay::decode(&replay.encode()).unwrap(), replay);
+    }
+
+    #[test]
+    fn replay_reaches_the_recorded_outcome() {
+        let mut app = headless::app();
+        headless::start_run(&mut app, SEED, GameMode::Campaign);
+
+        // sweep across the screen firing both torches, switching weapons now and then
+        run_until_finished(&mut app, |app, frame| {
+            let left = (frame / 90) % 2 == 0;
+            headless::set_key(app, KeyCode::A, left);
+            headless::set_key(app, KeyCode::D, !left);
+            headless::set_key(app, KeyCode::J, frame % 2 == 0);
+            headless::set_key(app, KeyCode::K, frame % 2 == 1);
+            headless::set_key(app, KeyCode::Key2, frame % 600 == 300);
+        });
+
+        let recorded = Replay::decode(&app.world.resource::<LastReplay>().0.encode()).unwrap();
+        assert_eq!(recorded.score, app.world.resource::<Scoreboard>().score);
+
+        let mut app = headless::app();
+        headless::start_run(&mut app, recorded.seed, recorded.mode);
+        app.world.resource_mut::<Global>().set_difficulty(recorded.difficulty);
+        app.world.resource_mut::<Global>().set_players(recorded.players);
+        app.world.insert_resource(ReplayPlayback::new(recorded.clone()));
+
+        // the keyboard is ignored while the replay drives the ship
+        run_until_finished(&mut app, |app, _| headless::set_key(app, KeyCode::A, true));
+
+        let playback = app.world.resource::<ReplayPlayback>();
+        assert!(playback.finished);
+        assert!(!playback.diverged);
+        assert_eq!(app.world.resource::<Scoreboard>().score, recorded.score);
+        assert_eq!(playback.wave, recorded.wave);
+    }
+}
@@ -0,0 +1,50 @@
+use rand::{random, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+const GAMEPLAY_STREAM: u64 = 0;
+const COSMETIC_STREAM: u64 = 1;
+
+/*
+  All randomness of a run comes from here, so a seed reproduces the run.
+  Gameplay and cosmetics draw from separate streams, so effects that only change the look
+  (like the victory bullets) never shift what the aliens do.
+*/
+pub struct GameRng {
+    seed: u64,
+    gameplay: ChaCha8Rng,
+    cosmetic: ChaCha8Rng,
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> GameRng {
+        let mut gameplay = ChaCha8Rng::seed_from_u64(seed);
+        gameplay.set_stream(GAMEPLAY_STREAM);
+        let mut cosmetic = ChaCha8Rng::seed_from_u64(seed);
+        cosmetic.set_stream(COSMETIC_STREAM);
+
+        GameRng { seed, gameplay, cosmetic }
+    }
+
+    pub fn from_entropy() -> GameRng {
+        GameRng::new(random())
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /*
+      Rewinds both streams, so the next run replays this seed from the start
+    */
+    pub fn reseed(&mut self) {
+        *self = GameRng::new(self.seed);
+    }
+
+    pub fn gameplay(&mut self) -> &mut ChaCha8Rng {
+        &mut self.gameplay
+    }
+
+    pub fn cosmetic(&mut self) -> &mut ChaCha8Rng {
+        &mut self.cosmetic
+    }
+}
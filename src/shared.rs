@@ -1,9 +1,22 @@
 use benimator::FrameRate;
 use bevy::prelude::*;
-use rand::random;
-use std::{collections::HashMap, time::Duration};
-
-use crate::{aliens::{Rylo, Aris, Zorg}, player::{SHIP_BULLET_SIZE, Ship, HealthDisplayHeart}, Scoreboard, gameover::GameOverMenu, Global};
+use rand::Rng;
+use std::{collections::HashMap, f32::consts::PI, time::Duration};
+
+use crate::{
+    aliens::Alien,
+    boss::{BossExplosion, BossHealthBar},
+    bunkers::BunkerCell,
+    divers::Diving,
+    gameover::GameOverMenu,
+    patterns::Seeker,
+    player::{HealthDisplayHeart, Ship},
+    powerups::{PowerUp, PowerUpHud},
+    rng::GameRng,
+    spatial_grid::SpatialGrid,
+    weapons::{Piercing, WeaponHud},
+    Explosion, Global, Scoreboard,
+};
 
 pub const TIME_STEP: f32 = 1.0 / 60.0;
 pub const CAMERA_LEVEL: f32 = 1.0;
@@ -20,15 +33,17 @@ pub const LEFT_WALL: f32 = -WINDOW_WIDTH / 2.;
 pub const RIGHT_WALL: f32 = WINDOW_WIDTH / 2.;
 pub const WALL_THICKNESS: f32 = 10.;
 
+// aliens that cross the invasion line have reached the ship's row
+pub const INVASION_LINE: f32 = BOTTOM_WALL + 90.;
+pub const INVASION_DAMAGE: u32 = 2;
+
 // bullet
 pub const BULLET_SIZE: Vec2 = Vec2::new(20.0, 40.0);
-pub const SHIP_BULLET_SPEED: f32 = 350.0;
-pub const SHIP_BULLET_INITIAL_GAP: f32 = 5.;
 pub const BULLET_LAYER: f32 = 1.0;
+// how far past the walls a bullet can travel before it is despawned
+pub const BULLET_CULLING_MARGIN: f32 = 100.0;
 
 // background
-pub const BACKGROUND_FONT_COLOR: Color = Color::rgb(1.0, 1.0, 1.0);
-pub const BACKGROUND_FONT_SIZE: f32 = 30.0;
 pub const BACKGROUND_LEVEL: f32 = -1.0;
 
 // scoreboard
@@ -37,6 +52,9 @@ pub const SCOREBOARD_FONT_SIZE: f32 = 30.0;
 pub const SCOREBOARD_PADDING_TOP: Val = Val::Px(8.0);
 pub const SCOREBOARD_PADDING_LEFT: Val = Val::Px(10.0);
 
+// collisions
+pub const COLLISION_CELL_SIZE: f32 = 80.0;
+
 // explosion
 pub const EXPLOSION_SIZE: f32 = 0.3;
 pub const EXPLOSION_FRAME_DURATION_IN_MILLIS: u64 = 20;
@@ -61,35 +79,114 @@ pub struct Collider {
 #[derive(Component, Deref, DerefMut)]
 pub struct Velocity(pub Vec2);
 
-#[derive(Component, PartialEq)]
+#[derive(Component, Clone, Copy, Debug, PartialEq)]
 pub enum Bullet {
     Ship,
     Alien,
 }
 
+pub struct BulletCulling {
+    pub margin: f32,
+}
+
+impl Default for BulletCulling {
+    fn default() -> Self {
+        BulletCulling {
+            margin: BULLET_CULLING_MARGIN,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ColliderKind {
+    Ship,
+    Alien,
+    Bunker,
+    Seeker,
+    Bullet(Bullet),
+}
+
+/*
+  Broad phase for every Collider, rebuilt once per fixed step
+*/
+#[derive(Deref, DerefMut)]
+pub struct CollisionGrid(SpatialGrid<(Entity, ColliderKind)>);
+
+impl Default for CollisionGrid {
+    fn default() -> Self {
+        CollisionGrid(SpatialGrid::new(
+            Vec2::new(LEFT_WALL, BOTTOM_WALL),
+            Vec2::new(RIGHT_WALL, TOP_WALL),
+            COLLISION_CELL_SIZE,
+        ))
+    }
+}
+
+pub struct BulletHitAlien {
+    pub bullet: Entity,
+    pub alien: Entity,
+    pub position: Vec3,
+}
+
+pub struct BulletHitShip {
+    pub bullet: Entity,
+    pub ship: Entity,
+    pub position: Vec3,
+}
+
+pub struct BulletHitBunker {
+    pub bullet: Entity,
+    pub cell: Entity,
+}
+
+/*
+  A diving alien flying into a ship, see divers.rs
+*/
+pub struct AlienHitShip {
+    pub alien: Entity,
+    pub ship: Entity,
+    pub position: Vec3,
+}
+
+/*
+  A ship bullet shooting down an alien Seeker
+*/
+pub struct BulletHitSeeker {
+    pub bullet: Entity,
+    pub seeker: Entity,
+}
+
+/*
+  Sent once for the blow that destroys an alien
+*/
+pub struct AlienDestroyed {
+    pub position: Vec3,
+    pub drop_chance: f32,
+}
+
 pub trait DurationGenerator {
-    fn sample(&self) -> Duration; 
+    fn sample(&self, rng: &mut impl Rng) -> Duration; 
 }
 
 pub struct AtMost(pub f32);
 
 impl DurationGenerator for AtMost {
-    fn sample(&self) -> Duration {
-        duration_at_most(self.0)
+    fn sample(&self, rng: &mut impl Rng) -> Duration {
+        duration_at_most(self.0, rng)
     }
 }
 pub struct Between(pub f32, pub f32); 
 
 impl DurationGenerator for Between {
-    fn sample(&self) -> Duration {
-        duration_between(self.0, self.1)
+    fn sample(&self, rng: &mut impl Rng) -> Duration {
+        duration_between(self.0, self.1, rng)
     }
 }
 
 pub struct Fixed(pub f32);
 
 impl DurationGenerator for Fixed {
-    fn sample(&self) -> Duration {
+    fn sample(&self, _rng: &mut impl Rng) -> Duration {
         Duration::from_secs_f32(self.0)
     }
 }
@@ -101,11 +198,11 @@ pub enum DurationType {
 }
 
 impl DurationGenerator for DurationType {
-    fn sample(&self) -> Duration {
+    fn sample(&self, rng: &mut impl Rng) -> Duration {
         match &self {
-            DurationType::AtMost(gen)  => gen.sample(),
-            DurationType::Between(gen) => gen.sample(),
-            DurationType::Fixed(gen)   => gen.sample()
+            DurationType::AtMost(gen)  => gen.sample(rng),
+            DurationType::Between(gen) => gen.sample(rng),
+            DurationType::Fixed(gen)   => gen.sample(rng)
         }
     }
 }
@@ -126,9 +223,9 @@ impl ShootingCooldown {
             duration 
         } 
     }
-    pub fn new(duration: DurationType) -> Self {
+    pub fn new(duration: DurationType, rng: &mut impl Rng) -> Self {
         ShootingCooldown {
-            timer: Timer::new(duration.sample(), false),
+            timer: Timer::new(duration.sample(rng), false),
             duration 
         } 
     }
@@ -141,10 +238,17 @@ impl ShootingCooldown {
         self.timer.finished()
     }
 
-    pub fn reset(&mut self) {
-        self.timer.set_duration(self.duration.sample());
+    pub fn reset(&mut self, rng: &mut impl Rng) {
+        self.timer.set_duration(self.duration.sample(rng));
         self.timer.reset();
     }
+
+    /*
+      Takes effect the next time the cooldown is reset
+    */
+    pub fn set_duration(&mut self, duration: DurationType) {
+        self.duration = duration;
+    }
 }
 
 pub struct Sprites {
@@ -162,7 +266,7 @@ impl Sprites {
     }
 
     pub fn get(&self, sprite_name: &str) -> Handle<Image> {
-        self.sprites.get(sprite_name).expect(format!("Sprite ({}) not found", sprite_name).as_str()).clone()
+        self.sprites.get(sprite_name).unwrap_or_else(|| panic!("Sprite ({}) not found", sprite_name)).clone()
     }
 }
 
@@ -204,46 +308,16 @@ impl BulletBundle {
         } 
     }
 
-    pub fn from_aris(translation: Vec2, sprite: Handle<Image>) -> BulletBundle {
-        BulletBundle::new(
-            translation, 
-            sprite, 
-            BULLET_SIZE, 
-            Velocity(Vec2::new(0., -Aris::BULLET_SPEED)), 
-            0.0,
-            Bullet::Alien
-        )
-    }
-
-    pub fn from_rylo(translation: Vec2, sprite: Handle<Image>) -> BulletBundle {
-        BulletBundle::new(
-            translation, 
-            sprite, 
-            BULLET_SIZE, 
-            Velocity(Vec2::new(0., -Rylo::BULLET_SPEED)), 
-            0.0,
-            Bullet::Alien
-        )
-    }
-
-    pub fn from_zorg(translation: Vec2, sprite: Handle<Image>, velocity: Velocity, rotation: f32) -> BulletBundle {
-        BulletBundle::new(
-            translation, 
-            sprite, 
-            Zorg::BULLET_SIZE, 
-            velocity,
-            rotation,
-            Bullet::Alien
-        ) 
-    }
-
-    pub fn from_ship(translation: Vec2, sprite: Handle<Image>) -> BulletBundle {
+    /*
+      angle is in degrees, positive angles lean left
+    */
+    pub fn from_ship(translation: Vec2, sprite: Handle<Image>, size: Vec2, speed: f32, angle: f32) -> BulletBundle {
         BulletBundle::new(
             translation, 
             sprite, 
-            SHIP_BULLET_SIZE, 
-            Velocity(Vec2::new(0., SHIP_BULLET_SPEED)), 
-            0.0,
+            size, 
+            Velocity(Vec2::new(-angle.to_radians().sin(), angle.to_radians().cos()) * speed), 
+            angle,
             Bullet::Ship
         )
     }
@@ -274,6 +348,19 @@ impl AnimationBundle {
             animation_state: AnimationState::default()
         }
     }
+
+    /*
+      Cycles through frame indices only, for components that keep their own images
+    */
+    pub fn from_frames(frames: usize, frame_duration: u64) -> AnimationBundle {
+        AnimationBundle {
+            animation: BAnimation(benimator::Animation::from_indices(
+                0..frames,
+                FrameRate::from_frame_duration(Duration::from_millis(frame_duration)),
+            )),
+            animation_state: AnimationState::default()
+        }
+    }
 }
 
 // TODO: use readonly public crate
@@ -374,13 +461,38 @@ impl WallBundle {
     }
 }
 
-pub fn duration_between(min_time: f32, max_time: f32) -> Duration {
-    let duration = min_time + random::<f32>() * (max_time - min_time);
+/*
+  The explosion a destroyed alien leaves behind, scale is relative to EXPLOSION_SIZE
+*/
+pub fn spawn_explosion(commands: &mut Commands, animations: &Animations, position: Vec3, scale: f32) {
+    let explosion = animations.get("EXPLOSION");
+    let texture_atlas = match &explosion.image_data {
+        ImageData::TextureAtlas(texture_atlas) => texture_atlas.clone(),
+        _ => panic!("Explosion is stored as a texture atlas!"),
+    };
+
+    commands
+        .spawn()
+        .insert_bundle(SpriteSheetBundle {
+            texture_atlas,
+            transform: Transform {
+                translation: position,
+                scale: Vec3::splat(EXPLOSION_SIZE * scale),
+                ..default()
+            },
+            ..default()
+        })
+        .insert_bundle(AnimationBundle::from_animation(explosion))
+        .insert(Explosion);
+}
+
+pub fn duration_between(min_time: f32, max_time: f32, rng: &mut impl Rng) -> Duration {
+    let duration = min_time + rng.gen::<f32>() * (max_time - min_time);
     Duration::from_secs_f32(duration)
 }
 
-pub fn duration_at_most(max_time: f32) -> Duration {
-    let duration = random::<f32>() * max_time;
+pub fn duration_at_most(max_time: f32, rng: &mut impl Rng) -> Duration {
+    let duration = rng.gen::<f32>() * max_time;
     Duration::from_secs_f32(duration)
 }
 
@@ -390,6 +502,148 @@ pub fn update_shooting_cooldowns(mut query: Query<&mut ShootingCooldown>) {
     }
 }
 
+/*
+  Turns velocity toward to_target by at most max_turn radians, keeping its speed
+*/
+pub fn turn_towards(velocity: Vec2, to_target: Vec2, max_turn: f32) -> Vec2 {
+    let heading = velocity.y.atan2(velocity.x);
+
+    // shortest signed turn, wrapped into [-PI, PI]
+    let mut turn = to_target.y.atan2(to_target.x) - heading;
+    if turn > PI {
+        turn -= 2.0 * PI;
+    } else if turn < -PI {
+        turn += 2.0 * PI;
+    }
+    let heading = heading + turn.clamp(-max_turn, max_turn);
+
+    Vec2::new(heading.cos(), heading.sin()) * velocity.length()
+}
+
+/*
+  Each bullet reports at most one hit: the first alien or seeker (for ship bullets) or ship (for alien bullets) it overlaps.
+  Piercing bullets report every alien and seeker they overlap. Bunkers stop every bullet, piercing ones included.
+  Every alien overlapping a ship is reported as well.
+*/
+pub fn detect_collisions(
+    mut grid: ResMut<CollisionGrid>,
+    collider_query: Query<(
+        Entity,
+        &Transform,
+        &Collider,
+        Option<&Bullet>,
+        Option<&Alien>,
+        Option<&Ship>,
+        Option<&BunkerCell>,
+        Option<&Seeker>,
+        Option<&Piercing>,
+    )>,
+    mut alien_hits: EventWriter<BulletHitAlien>,
+    mut ship_hits: EventWriter<BulletHitShip>,
+    mut bunker_hits: EventWriter<BulletHitBunker>,
+    mut seeker_hits: EventWriter<BulletHitSeeker>,
+    mut alien_rams: EventWriter<AlienHitShip>,
+    diving_query: Query<With<Diving>>,
+) {
+    grid.clear();
+
+    for (entity, transform, collider, bullet, alien, ship, bunker_cell, seeker, _) in collider_query.iter() {
+        let kind = match (bullet, alien, ship, bunker_cell) {
+            (Some(_), _, _, _) if seeker.is_some() => ColliderKind::Seeker,
+            (Some(bullet), _, _, _) => ColliderKind::Bullet(*bullet),
+            (_, Some(_), _, _) => ColliderKind::Alien,
+            (_, _, Some(_), _) => ColliderKind::Ship,
+            (_, _, _, Some(_)) => ColliderKind::Bunker,
+            _ => continue,
+        };
+        grid.insert((entity, kind), transform.translation.truncate(), collider.size);
+    }
+
+    for (bullet_entity, transform, collider, bullet, _, _, _, _, piercing) in collider_query.iter() {
+        let bullet = match bullet {
+            Some(bullet) => *bullet,
+            None => continue,
+        };
+        let is_target = |kind: ColliderKind| match bullet {
+            Bullet::Ship => kind == ColliderKind::Alien || kind == ColliderKind::Seeker,
+            Bullet::Alien => kind == ColliderKind::Ship,
+        };
+
+        let mut targets = Vec::new();
+        let mut bunker_cell = None;
+        grid.query(transform.translation.truncate(), collider.size, |item| {
+            let (entity, kind) = item.value;
+            if kind == ColliderKind::Bunker && bunker_cell.is_none() {
+                bunker_cell = Some(entity);
+            } else if is_target(kind) && (targets.is_empty() || piercing.is_some()) {
+                targets.push((entity, kind));
+            }
+        });
+
+        if let Some(cell) = bunker_cell {
+            bunker_hits.send(BulletHitBunker { bullet: bullet_entity, cell });
+            continue;
+        }
+
+        for (target, kind) in targets {
+            match bullet {
+                Bullet::Ship if kind == ColliderKind::Seeker => seeker_hits.send(BulletHitSeeker {
+                    bullet: bullet_entity,
+                    seeker: target,
+                }),
+                Bullet::Ship => alien_hits.send(BulletHitAlien {
+                    bullet: bullet_entity,
+                    alien: target,
+                    position: transform.translation,
+                }),
+                Bullet::Alien => ship_hits.send(BulletHitShip {
+                    bullet: bullet_entity,
+                    ship: target,
+                    position: transform.translation,
+                }),
+            }
+        }
+    }
+
+    for (ship_entity, transform, collider, _, _, ship, _, _, _) in collider_query.iter() {
+        if ship.is_none() {
+            continue;
+        }
+
+        // only a diving alien flies low enough to ram, the rest of the formation invades instead
+        grid.query(transform.translation.truncate(), collider.size, |item| {
+            if let (alien, ColliderKind::Alien) = item.value {
+                if diving_query.get(alien).is_err() {
+                    return;
+                }
+                alien_rams.send(AlienHitShip {
+                    alien,
+                    ship: ship_entity,
+                    position: item.center.extend(transform.translation.z),
+                });
+            }
+        });
+    }
+}
+
+pub fn despawn_offscreen_bullets(
+    mut commands: Commands,
+    culling: Res<BulletCulling>,
+    bullet_query: Query<(Entity, &Transform), With<Bullet>>,
+) {
+    for (bullet, transform) in bullet_query.iter() {
+        let translation = transform.translation;
+
+        if translation.x < LEFT_WALL - culling.margin
+            || translation.x > RIGHT_WALL + culling.margin
+            || translation.y < BOTTOM_WALL - culling.margin
+            || translation.y > TOP_WALL + culling.margin
+        {
+            commands.entity(bullet).despawn_recursive();
+        }
+    }
+}
+
 pub fn reset_game(
     mut commands: Commands,
     ship_query: Query<Entity, With<Ship>>,
@@ -397,12 +651,20 @@ pub fn reset_game(
     menu_query: Query<Entity, With<GameOverMenu>>,
     scoreboard_query: Query<Entity, With<Scoreboard>>,
     heart_query: Query<Entity, With<HealthDisplayHeart>>,
-    mut global: ResMut<Global>
+    power_up_query: Query<Entity, Or<(With<PowerUp>, With<PowerUpHud>)>>,
+    weapon_hud_query: Query<Entity, With<WeaponHud>>,
+    boss_query: Query<Entity, Or<(With<BossHealthBar>, With<BossExplosion>)>>,
+    bunker_query: Query<Entity, With<BunkerCell>>,
+    mut global: ResMut<Global>,
+    mut rng: ResMut<GameRng>,
 ) {
     global.reset();
+    // retrying replays the same seed
+    rng.reseed();
 
-    let ship = ship_query.single();
-    commands.entity(ship).despawn_recursive();
+    for ship in ship_query.iter() {
+        commands.entity(ship).despawn_recursive();
+    }
 
     for bullet in bullet_query.iter() {
         commands.entity(bullet).despawn();
@@ -419,4 +681,54 @@ pub fn reset_game(
     for heart_entity in heart_query.iter() {
         commands.entity(heart_entity).despawn();
     }
+
+    for power_up_entity in power_up_query.iter() {
+        commands.entity(power_up_entity).despawn();
+    }
+
+    for weapon_hud in weapon_hud_query.iter() {
+        commands.entity(weapon_hud).despawn();
+    }
+
+    for boss_entity in boss_query.iter() {
+        commands.entity(boss_entity).despawn_recursive();
+    }
+
+    for bunker_cell in bunker_query.iter() {
+        commands.entity(bunker_cell).despawn();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn angle(vector: Vec2) -> f32 {
+        vector.y.atan2(vector.x).to_degrees()
+    }
+
+    #[test]
+    fn turn_towards_reaches_a_close_target() {
+        let turned = turn_towards(Vec2::new(100.0, 0.0), Vec2::new(1.0, 0.1), 0.5);
+
+        assert!((turned.normalize() - Vec2::new(1.0, 0.1).normalize()).length() < 0.001);
+    }
+
+    #[test]
+    fn turn_towards_is_clamped_and_keeps_speed() {
+        let turned = turn_towards(Vec2::new(100.0, 0.0), Vec2::new(0.0, 1.0), 10f32.to_radians());
+
+        assert!((angle(turned) - 10.0).abs() < 0.01, "{}", angle(turned));
+        assert!((turned.length() - 100.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn turn_towards_takes_the_short_way_round() {
+        // 170 degrees to -170 degrees is 20 degrees anticlockwise across the wrap, not 340 clockwise
+        let from = Vec2::new(170f32.to_radians().cos(), 170f32.to_radians().sin());
+        let to = Vec2::new((-170f32).to_radians().cos(), (-170f32).to_radians().sin());
+        let turned = turn_towards(from, to, 5f32.to_radians());
+
+        assert!((angle(turned) - 175.0).abs() < 0.01, "{}", angle(turned));
+    }
 }
@@ -0,0 +1,78 @@
+use bevy::{
+    ecs::schedule::{IntoSystemDescriptor, SingleThreadedExecutor},
+    prelude::*,
+};
+use iyes_loopless::prelude::*;
+use std::time::Duration;
+
+use crate::shared::TIME_STEP;
+
+pub const SIMULATION: &str = "Simulation";
+
+/*
+  The ordered parts of every fixed step. Each phase is its own single threaded child stage,
+  so commands from one phase land before the next, and the systems within a phase run in the order
+  they were added (the gameplay rng is drawn in the same order every run, which replays rely on).
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SimulationPhase {
+    Input,     // the ship acts on the player's input
+    Ai,        // aliens decide where to go and fire, cooldowns tick
+    Movement,  // everything that moves on its own
+    Collision, // overlaps are detected against the post movement positions
+    Damage,    // hits are applied, scored and checked for the end of the run
+    Cleanup,   // timers, displays and whatever left the screen
+}
+
+impl SimulationPhase {
+    const ALL: [SimulationPhase; 6] = [
+        SimulationPhase::Input,
+        SimulationPhase::Ai,
+        SimulationPhase::Movement,
+        SimulationPhase::Collision,
+        SimulationPhase::Damage,
+        SimulationPhase::Cleanup,
+    ];
+}
+
+pub trait SimulationApp {
+    fn add_simulation_system<Params>(
+        &mut self,
+        phase: SimulationPhase,
+        system: impl IntoSystemDescriptor<Params>,
+    ) -> &mut Self;
+
+    fn add_simulation_system_set(&mut self, phase: SimulationPhase, system_set: SystemSet) -> &mut Self;
+}
+
+impl SimulationApp for App {
+    fn add_simulation_system<Params>(
+        &mut self,
+        phase: SimulationPhase,
+        system: impl IntoSystemDescriptor<Params>,
+    ) -> &mut Self {
+        self.add_fixed_timestep_system(SIMULATION, phase as usize, system)
+    }
+
+    fn add_simulation_system_set(&mut self, phase: SimulationPhase, system_set: SystemSet) -> &mut Self {
+        self.add_fixed_timestep_system_set(SIMULATION, phase as usize, system_set)
+    }
+}
+
+/*
+  Has to be added before the plugins that register simulation systems
+*/
+pub struct SimulationPlugin;
+
+impl Plugin for SimulationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_fixed_timestep_before_stage(CoreStage::Update, Duration::from_secs_f32(TIME_STEP), SIMULATION);
+
+        // the first child stage comes parallel, the rest are added single threaded to match it
+        app.get_fixed_timestep_child_substage_mut::<SystemStage>(SIMULATION, 0)
+            .set_executor(Box::new(SingleThreadedExecutor));
+        for _ in &SimulationPhase::ALL[1..] {
+            app.add_fixed_timestep_custom_child_stage(SIMULATION, SystemStage::single_threaded());
+        }
+    }
+}
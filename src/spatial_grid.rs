@@ -0,0 +1,161 @@
+use bevy::math::Vec2;
+
+/*
+  A uniform grid over a fixed area, used as the broad phase for collisions.
+  Items are axis aligned boxes, and anything outside the area is clamped into the border cells.
+*/
+pub struct SpatialGrid<T> {
+    origin: Vec2,
+    cell_size: f32,
+    columns: usize,
+    rows: usize,
+    cells: Vec<Vec<usize>>,
+    items: Vec<GridItem<T>>,
+}
+
+pub struct GridItem<T> {
+    pub value: T,
+    pub center: Vec2,
+    pub size: Vec2,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct CellRange {
+    min: (usize, usize),
+    max: (usize, usize),
+}
+
+impl<T> SpatialGrid<T> {
+    pub fn new(min: Vec2, max: Vec2, cell_size: f32) -> SpatialGrid<T> {
+        let columns = ((max.x - min.x) / cell_size).ceil().max(1.0) as usize;
+        let rows = ((max.y - min.y) / cell_size).ceil().max(1.0) as usize;
+
+        SpatialGrid {
+            origin: min,
+            cell_size,
+            columns,
+            rows,
+            cells: vec![Vec::new(); columns * rows],
+            items: Vec::new(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for cell in self.cells.iter_mut() {
+            cell.clear();
+        }
+        self.items.clear();
+    }
+
+    pub fn insert(&mut self, value: T, center: Vec2, size: Vec2) {
+        let index = self.items.len();
+        let range = self.cell_range(center, size);
+
+        for row in range.min.1..=range.max.1 {
+            for col in range.min.0..=range.max.0 {
+                self.cells[row * self.columns + col].push(index);
+            }
+        }
+
+        self.items.push(GridItem { value, center, size });
+    }
+
+    /*
+      Visits every item overlapping the box exactly once
+    */
+    pub fn query(&self, center: Vec2, size: Vec2, mut visit: impl FnMut(&GridItem<T>)) {
+        let range = self.cell_range(center, size);
+
+        for row in range.min.1..=range.max.1 {
+            for col in range.min.0..=range.max.0 {
+                for &index in self.cells[row * self.columns + col].iter() {
+                    let item = &self.items[index];
+
+                    if !overlaps(center, size, item.center, item.size) {
+                        continue;
+                    }
+
+                    // an item spanning several cells is only reported from the first cell both boxes share
+                    let item_range = self.cell_range(item.center, item.size);
+                    let first_shared_cell = (range.min.0.max(item_range.min.0), range.min.1.max(item_range.min.1));
+                    if first_shared_cell == (col, row) {
+                        visit(item);
+                    }
+                }
+            }
+        }
+    }
+
+    fn cell(&self, point: Vec2) -> (usize, usize) {
+        let local = (point - self.origin) / self.cell_size;
+        let col = (local.x.floor().max(0.0) as usize).min(self.columns - 1);
+        let row = (local.y.floor().max(0.0) as usize).min(self.rows - 1);
+
+        (col, row)
+    }
+
+    fn cell_range(&self, center: Vec2, size: Vec2) -> CellRange {
+        CellRange {
+            min: self.cell(center - size / 2.0),
+            max: self.cell(center + size / 2.0),
+        }
+    }
+}
+
+pub fn overlaps(a_center: Vec2, a_size: Vec2, b_center: Vec2, b_size: Vec2) -> bool {
+    let distance = (a_center - b_center).abs();
+    let reach = (a_size + b_size) / 2.0;
+
+    distance.x < reach.x && distance.y < reach.y
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid() -> SpatialGrid<u32> {
+        SpatialGrid::new(Vec2::new(-100.0, -100.0), Vec2::new(100.0, 100.0), 20.0)
+    }
+
+    fn found(grid: &SpatialGrid<u32>, center: Vec2, size: Vec2) -> Vec<u32> {
+        let mut values = Vec::new();
+        grid.query(center, size, |item| values.push(item.value));
+        values.sort();
+        values
+    }
+
+    #[test]
+    fn an_item_spanning_cells_is_reported_once() {
+        let mut grid = grid();
+        // covers the corner where four cells meet
+        grid.insert(1, Vec2::new(0.0, 0.0), Vec2::new(30.0, 30.0));
+
+        assert_eq!(found(&grid, Vec2::ZERO, Vec2::new(200.0, 200.0)), vec![1]);
+        assert_eq!(found(&grid, Vec2::new(5.0, 5.0), Vec2::new(25.0, 25.0)), vec![1]);
+        assert_eq!(found(&grid, Vec2::new(-12.0, 12.0), Vec2::new(4.0, 4.0)), vec![1]);
+    }
+
+    #[test]
+    fn negative_coordinates() {
+        let mut grid = grid();
+        grid.insert(1, Vec2::new(-55.0, -75.0), Vec2::new(10.0, 10.0));
+        grid.insert(2, Vec2::new(55.0, 75.0), Vec2::new(10.0, 10.0));
+
+        assert_eq!(found(&grid, Vec2::new(-50.0, -70.0), Vec2::new(10.0, 10.0)), vec![1]);
+        assert_eq!(found(&grid, Vec2::new(-55.0, 75.0), Vec2::new(10.0, 10.0)), Vec::<u32>::new());
+    }
+
+    #[test]
+    fn items_outside_the_grid() {
+        let mut grid = grid();
+        grid.insert(1, Vec2::new(300.0, 0.0), Vec2::new(10.0, 10.0));
+        grid.insert(2, Vec2::new(-95.0, -150.0), Vec2::new(20.0, 120.0));
+
+        // clamped into the border cells, but only reported where they really are
+        assert_eq!(found(&grid, Vec2::new(300.0, 0.0), Vec2::new(10.0, 10.0)), vec![1]);
+        assert_eq!(found(&grid, Vec2::new(95.0, 0.0), Vec2::new(10.0, 10.0)), Vec::<u32>::new());
+        assert_eq!(found(&grid, Vec2::new(-95.0, -95.0), Vec2::new(10.0, 10.0)), vec![2]);
+        assert_eq!(found(&grid, Vec2::new(-95.0, -200.0), Vec2::new(10.0, 10.0)), vec![2]);
+        assert_eq!(found(&grid, Vec2::ZERO, Vec2::new(1000.0, 1000.0)), vec![1, 2]);
+    }
+}
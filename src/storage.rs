@@ -0,0 +1,50 @@
+/*
+  Small key/value persistence. Native builds write one file per key (the key is the file name) to the platform data directory,
+  web builds use the browser's localStorage.
+*/
+
+#[cfg(not(target_arch = "wasm32"))]
+mod platform {
+    use std::{fs, path::PathBuf};
+
+    const DATA_DIRECTORY: &str = "rust-wars";
+
+    fn path(key: &str) -> Option<PathBuf> {
+        Some(dirs::data_dir()?.join(DATA_DIRECTORY).join(key))
+    }
+
+    pub fn load(key: &str) -> Option<String> {
+        fs::read_to_string(path(key)?).ok()
+    }
+
+    pub fn save(key: &str, value: &str) -> Result<(), String> {
+        let path = path(key).ok_or("no data directory on this platform")?;
+
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory).map_err(|error| error.to_string())?;
+        }
+        fs::write(&path, value).map_err(|error| error.to_string())
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+mod platform {
+    const KEY_PREFIX: &str = "rust-wars.";
+
+    fn local_storage() -> Option<web_sys::Storage> {
+        web_sys::window()?.local_storage().ok()?
+    }
+
+    pub fn load(key: &str) -> Option<String> {
+        local_storage()?.get_item(&format!("{}{}", KEY_PREFIX, key)).ok()?
+    }
+
+    pub fn save(key: &str, value: &str) -> Result<(), String> {
+        local_storage()
+            .ok_or("localStorage is unavailable")?
+            .set_item(&format!("{}{}", KEY_PREFIX, key), value)
+            .map_err(|error| format!("{:?}", error))
+    }
+}
+
+pub use platform::{load, save};
@@ -1,37 +1,969 @@
-use crate::aliens::Wave;
+use bevy::{
+    asset::{AssetLoader, AssetPath, HandleId, LoadContext, LoadState, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::BoxedFuture,
+};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::Deserialize;
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    bunkers::BunkerCell,
+    shared::{LEFT_WALL, RIGHT_WALL},
+    GameMode, Global, LOAD_WAVE_DURATION_IN_SECONDS,
+};
+
+pub const WAVE_MANIFEST_PATH: &str = "waves/campaign.manifest";
+// cells that never contain an alien
+const EMPTY_CELLS: [char; 2] = ['#', '.'];
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Movement {
+    // every walking alien walks and turns around on its own
+    #[default]
+    Individual,
+    // all walking aliens move as one block at the pace of the slowest, see aliens::Formation
+    Formation,
+}
+
+/*
+  How the aliens fly into their slots, see paths.rs for the curves
+*/
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Entry {
+    // each alien curves in from its own random spot above the middle of the screen
+    #[default]
+    Scatter,
+    // in single file from off the left edge, swooping low and rising into the slots
+    SwoopLeft,
+    // the same from off the right edge
+    SwoopRight,
+    // straight down from above each slot, weaving side to side
+    Weave,
+    // down the middle, once around a loop and out to the slots
+    Loop,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BunkerMode {
+    // every wave starts with fresh bunkers
+    #[default]
+    Reset,
+    // whatever is left of the previous wave's bunkers stays, new ones are only built when none are left
+    Persist,
+}
+
+/*
+  Bunkers spread evenly across the screen above the ships. Every character in the shape
+  other than a space is a cell that is chipped away by a single bullet.
+*/
+#[derive(Deserialize, Clone, Debug)]
+pub struct Bunkers {
+    pub count: u32,
+    pub shape: Vec<String>,
+    #[serde(default)]
+    pub mode: BunkerMode,
+}
+
+impl Bunkers {
+    /*
+      The width of the widest row of the shape, in cells
+    */
+    pub fn columns(&self) -> usize {
+        self.shape.iter().map(|row| row.chars().count()).max().unwrap_or(0)
+    }
+
+    /*
+      How far apart the centres of neighbouring bunkers are
+    */
+    pub fn spacing(&self) -> f32 {
+        (RIGHT_WALL - LEFT_WALL) / self.count.max(1) as f32
+    }
+}
+
+/*
+  What a character in the layout spawns, alien is an archetype name from the roster (see archetypes.rs).
+  Overrides multiply the wave's own multipliers.
+*/
+#[derive(Deserialize, Clone, Debug)]
+pub struct WaveCell {
+    pub alien: String,
+    #[serde(default)]
+    pub speed: Option<f32>,
+    #[serde(default)]
+    pub fire_rate: Option<f32>,
+    // hits needed to destroy the alien, defaults to the alien's own
+    #[serde(default)]
+    pub health: Option<u32>,
+    // scales the health, whether it is the alien's own or the one above
+    #[serde(default)]
+    pub health_multiplier: Option<f32>,
+}
+
+impl WaveCell {
+    /*
+      The hits needed to destroy an alien whose archetype has base_health, never less than one
+    */
+    pub fn health_for(&self, base_health: u32) -> u32 {
+        let health = self.health.unwrap_or(base_health);
+
+        match self.health_multiplier {
+            Some(multiplier) => ((health as f32 * multiplier).round() as u32).max(1),
+            None => health,
+        }
+    }
+}
+
+/*
+  Wave files are RON:
+  (
+      name: "First Contact",
+      intro: "They come in peace. Probably.",
+      speed: 1.0,
+      fire_rate: 1.0,
+      movement: Formation,
+      entry: SwoopLeft,
+      column_delay: 0.2,
+      legend: { 'A': (alien: "Aris", speed: 1.5), 'Z': (alien: "Zorg", health_multiplier: 2.0) },
+      layout: [
+          "AAaa##aaAA",
+      ],
+      bunkers: (count: 4, shape: [" ### ", "#####", "#   #"], mode: Persist),
+  )
+*/
+#[derive(Deserialize)]
+struct WaveFile {
+    name: String,
+    #[serde(default)]
+    intro: String,
+    #[serde(default = "default_multiplier")]
+    speed: f32,
+    #[serde(default = "default_multiplier")]
+    fire_rate: f32,
+    #[serde(default)]
+    load_duration: Option<f32>,
+    #[serde(default)]
+    movement: Movement,
+    #[serde(default)]
+    entry: Entry,
+    // seconds each column waits after the one to its left before flying in
+    #[serde(default)]
+    column_delay: f32,
+    #[serde(default)]
+    background: Option<String>,
+    #[serde(default)]
+    music: Option<String>,
+    #[serde(default)]
+    legend: HashMap<char, WaveCell>,
+    layout: Vec<String>,
+    #[serde(default)]
+    bunkers: Option<Bunkers>,
+}
+
+fn default_multiplier() -> f32 {
+    1.0
+}
+
+fn default_legend() -> HashMap<char, WaveCell> {
+    [('a', "Aris"), ('r', "Rylo"), ('z', "Zorg"), ('k', "Kiro"), ('d', "DevilFerris")]
+        .into_iter()
+        .map(|(symbol, alien)| {
+            let cell = WaveCell {
+                alien: alien.to_string(),
+                speed: None,
+                fire_rate: None,
+                health: None,
+                health_multiplier: None,
+            };
+            (symbol, cell)
+        })
+        .collect()
+}
+
+#[derive(TypeUuid, Clone)]
+#[uuid = "3c3f3d4e-5b7a-4f0e-9a43-2b1f6c1d8e70"]
+pub struct Wave {
+    pub name: String,
+    pub intro: String,
+    pub speed: f32,
+    pub fire_rate: f32,
+    pub load_duration: f32,
+    pub movement: Movement,
+    pub entry: Entry,
+    pub column_delay: f32,
+    pub background: Option<String>,
+    pub music: Option<String>,
+    pub legend: HashMap<char, WaveCell>,
+    pub layout: Vec<Vec<char>>,
+    // no bunkers at all when None
+    pub bunkers: Option<Bunkers>,
+}
+/*
+  The ordered list of waves that make up the campaign
+*/
+#[derive(TypeUuid)]
+#[uuid = "8f6c2a31-0d4b-4e57-b1a8-7e2f9c5d3b14"]
+pub struct WaveManifest {
+    pub waves: Vec<Handle<Wave>>,
+}
+
+pub struct Waves {
+    manifest: Handle<WaveManifest>,
+}
+
+impl Waves {
+    pub fn is_loaded(&self, asset_server: &AssetServer, manifests: &Assets<WaveManifest>) -> bool {
+        match manifests.get(&self.manifest) {
+            Some(manifest) => {
+                asset_server.get_group_load_state(manifest.waves.iter().map(HandleId::from)) == LoadState::Loaded
+            }
+            None => false,
+        }
+    }
+
+    pub fn get<'a>(&self, wave: u32, manifests: &Assets<WaveManifest>, waves: &'a Assets<Wave>) -> Option<&'a Wave> {
+        let manifest = manifests.get(&self.manifest).expect("wave manifest not loaded");
+        let handle = manifest.waves.get(wave as usize)?;
+
+        Some(waves.get(handle).expect("wave not loaded"))
+    }
+
+    /*
+      The wave being played. None once the campaign has been cleared.
+    */
+    pub fn current<'a>(
+        &self,
+        global: &Global,
+        manifests: &Assets<WaveManifest>,
+        waves: &'a Assets<Wave>,
+    ) -> Option<Cow<'a, Wave>> {
+        match global.mode() {
+            GameMode::Campaign => self.get(global.current_wave(), manifests, waves).map(Cow::Borrowed),
+            GameMode::Endless { seed } => Some(Cow::Owned(WaveGenerator::new(seed).generate(global.current_wave()))),
+        }
+    }
+}
+
+/*
+  Builds endless mode waves. The same seed and wave index always produce the same wave.
+*/
+pub struct WaveGenerator {
+    seed: u64,
+}
+
+impl WaveGenerator {
+    const ROWS: usize = 5;
+    const COLUMNS: usize = 10;
+    const INITIAL_DENSITY: f32 = 0.4;
+    const MAX_DENSITY: f32 = 0.95;
+    const DENSITY_PER_WAVE: f32 = 0.05;
+    const MAX_RYLO_CHANCE: f32 = 0.35;
+    const MAX_ZORG_CHANCE: f32 = 0.25;
+    const MAX_KIRO_CHANCE: f32 = 0.15;
+    // taken in turn, one a wave
+    const ENTRIES: [Entry; 5] = [Entry::Scatter, Entry::SwoopLeft, Entry::Weave, Entry::SwoopRight, Entry::Loop];
+    const COLUMN_DELAY: f32 = 0.1;
+    const SPEED_PER_WAVE: f32 = 0.05;
+    const MAX_SPEED: f32 = 2.0;
+    const FIRE_RATE_PER_WAVE: f32 = 0.1;
+    const MAX_FIRE_RATE: f32 = 3.0;
+    // every fifth wave is a boss wave
+    const BOSS_EVERY: u32 = 5;
+    // rows left clear for the boss
+    const BOSS_ROWS: usize = 2;
+    // each boss has this share of the roster's boss health more than the one before
+    const BOSS_HEALTH_GROWTH: f32 = 1.0 / 3.0;
+    const BUNKER_COUNT: u32 = 4;
+    const BUNKER_SHAPE: [&'static str; 4] = ["  ####  ", " ###### ", "########", "###  ###"];
+
+    pub fn new(seed: u64) -> WaveGenerator {
+        WaveGenerator { seed }
+    }
+
+    pub fn generate(&self, wave: u32) -> Wave {
+        // every wave gets its own stream, so wave n doesn't depend on how waves 0..n were generated
+        let mut rng = ChaCha8Rng::seed_from_u64(self.seed ^ (wave as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+        let difficulty = wave as f32;
+
+        let density = (WaveGenerator::INITIAL_DENSITY + WaveGenerator::DENSITY_PER_WAVE * difficulty)
+            .min(WaveGenerator::MAX_DENSITY);
+        let rylo_chance = (0.05 + 0.03 * difficulty).min(WaveGenerator::MAX_RYLO_CHANCE);
+        let zorg_chance = (0.02 * (difficulty - 1.0)).clamp(0.0, WaveGenerator::MAX_ZORG_CHANCE);
+        let kiro_chance = (0.02 * (difficulty - 2.0)).clamp(0.0, WaveGenerator::MAX_KIRO_CHANCE);
+
+        // the left half is mirrored onto the right half
+        let half_width = WaveGenerator::COLUMNS / 2;
+        let mut layout = vec![vec!['#'; WaveGenerator::COLUMNS]; WaveGenerator::ROWS];
+        let mut legend = default_legend();
+
+        let boss_wave = wave % WaveGenerator::BOSS_EVERY == WaveGenerator::BOSS_EVERY - 1;
+        if boss_wave {
+            // later bosses take more to bring down
+            let bosses_before = wave / WaveGenerator::BOSS_EVERY;
+            let multiplier = 1.0 + WaveGenerator::BOSS_HEALTH_GROWTH * bosses_before as f32;
+
+            if let Some(boss) = legend.get_mut(&'d') {
+                boss.health_multiplier = Some(multiplier);
+            }
+            layout[0][half_width] = 'd';
+        }
+        let first_escort_row = if boss_wave { WaveGenerator::BOSS_ROWS } else { 0 };
+
+        // a share of the slots rather than a roll per slot, so a later wave never has fewer aliens
+        let slots = (WaveGenerator::ROWS - first_escort_row) * half_width;
+        let filled = (density * slots as f32).round() as usize;
+
+        for slot in rand::seq::index::sample(&mut rng, slots, filled).iter() {
+            let (row, col) = (first_escort_row + slot / half_width, slot % half_width);
+
+            let roll = rng.gen::<f32>();
+            let cell = if roll < zorg_chance {
+                'z'
+            } else if roll < zorg_chance + kiro_chance {
+                'k'
+            } else if roll < zorg_chance + kiro_chance + rylo_chance {
+                'r'
+            } else {
+                'a'
+            };
+
+            layout[row][col] = cell;
+            layout[row][WaveGenerator::COLUMNS - 1 - col] = cell;
+        }
+
+        if layout.iter().flatten().all(|cell| EMPTY_CELLS.contains(cell)) {
+            layout[0][half_width - 1] = 'a';
+            layout[0][half_width] = 'a';
+        }
+
+        Wave {
+            name: if boss_wave { format!("Endless {}: Devil Ferris", wave + 1) } else { format!("Endless {}", wave + 1) },
+            intro: format!("Seed {}", self.seed),
+            speed: (1.0 + WaveGenerator::SPEED_PER_WAVE * difficulty).min(WaveGenerator::MAX_SPEED),
+            fire_rate: (1.0 + WaveGenerator::FIRE_RATE_PER_WAVE * difficulty).min(WaveGenerator::MAX_FIRE_RATE),
+            load_duration: LOAD_WAVE_DURATION_IN_SECONDS,
+            movement: Movement::Formation,
+            entry: WaveGenerator::ENTRIES[wave as usize % WaveGenerator::ENTRIES.len()],
+            column_delay: WaveGenerator::COLUMN_DELAY,
+            background: None,
+            music: None,
+            legend,
+            layout,
+            bunkers: Some(WaveGenerator::bunkers(boss_wave)),
+        }
+    }
+
+    /*
+      The bunkers wear down over the endless run and are rebuilt for each boss
+    */
+    fn bunkers(boss_wave: bool) -> Bunkers {
+        Bunkers {
+            count: WaveGenerator::BUNKER_COUNT,
+            shape: WaveGenerator::BUNKER_SHAPE.iter().map(|row| row.to_string()).collect(),
+            mode: if boss_wave { BunkerMode::Reset } else { BunkerMode::Persist },
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct WaveParseError {
+    pub file: PathBuf,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for WaveParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}: {}", self.file.display(), self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for WaveParseError {}
 
 impl Wave {
-    pub fn get_data(path: &str) -> Vec<&str> {
-        match path {
-            "assets/waves/wave_0.txt" => vec![
-                "AAAAAAAAAA",
-                "#AA#AA#AA#",
-                "##########",
-                "#AA#AA#AA#",
-                "AAAAAAAAAA"
-            ],
-            "assets/waves/wave_1.txt" => vec![
-                "aa#a##a#aa",
-                "#aaraaraa#",
-                "##########",
-                "#aaraaraa#",
-                "raaa##aaar",
-            ],
-            "assets/waves/wave_2.txt" => vec![
-                "aa#zrrz#aa",
-                "raaraaraar",
-                "zr#rzzr#rz",
-                "raa#aa#aar",
-                "#aaa##aaa#",
-            ],
-            "assets/waves/wave_3.txt" => vec![
-                "razzrrzzar",
-                "rraraararr",
-                "zrrrzzrrrz",
-                "raazaazaar",
-                "zaaazzaaaz",
-            ],
-            _ => panic!("Not a valid wave")
+    pub fn parse(file: &Path, bytes: &[u8]) -> Result<Wave, WaveParseError> {
+        let error = |line: usize, column: usize, message: String| WaveParseError {
+            file: file.to_path_buf(),
+            line,
+            column,
+            message,
+        };
+
+        let source = std::str::from_utf8(bytes).map_err(|utf8_error| {
+            let valid = &bytes[..utf8_error.valid_up_to()];
+            let line = valid.iter().filter(|byte| **byte == b'\n').count() + 1;
+            let column = valid.iter().rev().take_while(|byte| **byte != b'\n').count() + 1;
+
+            error(line, column, "wave files must be valid UTF-8".to_string())
+        })?;
+
+        let wave_file: WaveFile = ron::de::from_str(source)
+            .map_err(|ron_error| error(ron_error.position.line, ron_error.position.col, ron_error.code.to_string()))?;
+
+        Wave::check_numbers(source, &wave_file).map_err(|(line, column, message)| error(line, column, message))?;
+
+        let mut legend = default_legend();
+        legend.extend(wave_file.legend);
+
+        let mut layout: Vec<Vec<char>> = Vec::new();
+
+        for (row_index, row_source) in wave_file.layout.iter().enumerate() {
+            let (line, first_column) = Wave::locate_row(source, row_index);
+
+            let row: Vec<char> = row_source.chars().collect();
+            for (column_index, cell) in row.iter().enumerate() {
+                if !EMPTY_CELLS.contains(cell) && !legend.contains_key(cell) {
+                    return Err(error(
+                        line,
+                        first_column + column_index,
+                        format!("'{}' is not in the legend", cell),
+                    ));
+                }
+            }
+
+            if let Some(first_row) = layout.first() {
+                if row.len() != first_row.len() {
+                    return Err(error(
+                        line,
+                        first_column + row.len().min(first_row.len()),
+                        format!("row has {} cells but the first row has {}", row.len(), first_row.len()),
+                    ));
+                }
+            }
+
+            layout.push(row);
+        }
+
+        if layout.is_empty() {
+            return Err(error(1, 1, "wave has no rows".to_string()));
+        }
+
+        Ok(Wave {
+            name: wave_file.name,
+            intro: wave_file.intro,
+            speed: wave_file.speed,
+            fire_rate: wave_file.fire_rate,
+            load_duration: wave_file.load_duration.unwrap_or(LOAD_WAVE_DURATION_IN_SECONDS),
+            movement: wave_file.movement,
+            entry: wave_file.entry,
+            column_delay: wave_file.column_delay,
+            background: wave_file.background,
+            music: wave_file.music,
+            legend,
+            layout,
+            bunkers: wave_file.bunkers,
+        })
+    }
+
+    /*
+      Multipliers and durations that would stop a timer or send an alien nowhere, and bunkers that
+      can't be laid out, as the line, column and message of the first one found
+    */
+    fn check_numbers(source: &str, wave_file: &WaveFile) -> Result<(), (usize, usize, String)> {
+        let positive = |value: f32, field: &str, from: usize| {
+            if value.is_finite() && value > 0.0 {
+                return Ok(());
+            }
+            let (line, column) = Wave::locate_field(source, from, field);
+            Err((line, column, format!("{} must be a positive number, not {}", field, value)))
+        };
+
+        positive(wave_file.speed, "speed", 0)?;
+        positive(wave_file.fire_rate, "fire_rate", 0)?;
+        if let Some(load_duration) = wave_file.load_duration {
+            positive(load_duration, "load_duration", 0)?;
+        }
+        if !wave_file.column_delay.is_finite() || wave_file.column_delay < 0.0 {
+            let (line, column) = Wave::locate_field(source, 0, "column_delay");
+            return Err((line, column, format!("column_delay must be 0 or more, not {}", wave_file.column_delay)));
+        }
+
+        let legend_line = Wave::locate_line(source, 0, "legend");
+        let mut symbols: Vec<&char> = wave_file.legend.keys().collect();
+        symbols.sort();
+        for symbol in symbols {
+            let cell = &wave_file.legend[symbol];
+            let cell_line = Wave::locate_line(source, legend_line, &format!("'{}'", symbol));
+
+            if let Some(speed) = cell.speed {
+                positive(speed, "speed", cell_line)?;
+            }
+            if let Some(fire_rate) = cell.fire_rate {
+                positive(fire_rate, "fire_rate", cell_line)?;
+            }
+            if let Some(health_multiplier) = cell.health_multiplier {
+                positive(health_multiplier, "health_multiplier", cell_line)?;
+            }
         }
+
+        if let Some(bunkers) = &wave_file.bunkers {
+            let bunkers_line = Wave::locate_line(source, 0, "bunkers");
+
+            if bunkers.count == 0 {
+                let (line, column) = Wave::locate_field(source, bunkers_line, "count");
+                return Err((line, column, "bunkers need a count of at least 1, leave them out for none".to_string()));
+            }
+
+            let (line, column) = Wave::locate_field(source, bunkers_line, "shape");
+            if bunkers.columns() == 0 {
+                return Err((line, column, "bunker shape has no cells".to_string()));
+            }
+
+            let width = bunkers.columns() as f32 * BunkerCell::SIZE.x;
+            if width > bunkers.spacing() {
+                let fits = ((RIGHT_WALL - LEFT_WALL) / width) as u32;
+                return Err((
+                    line,
+                    column,
+                    format!(
+                        "{} bunkers {} cells wide don't fit side by side, at most {} do",
+                        bunkers.count,
+                        bunkers.columns(),
+                        fits
+                    ),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /*
+      The index of the first line at or after from that contains text, from itself when none do
+    */
+    fn locate_line(source: &str, from: usize, text: &str) -> usize {
+        source
+            .lines()
+            .enumerate()
+            .skip(from)
+            .find(|(_, line)| line.contains(text))
+            .map_or(from, |(line_index, _)| line_index)
+    }
+
+    /*
+      Finds the line and column of the first "field:" at or after the line with index from, for error reporting
+    */
+    fn locate_field(source: &str, from: usize, field: &str) -> (usize, usize) {
+        let key = format!("{}:", field);
+
+        source
+            .lines()
+            .enumerate()
+            .skip(from)
+            .find_map(|(line_index, line)| {
+                line.match_indices(&key)
+                    .map(|(column, _)| column)
+                    // not the end of a longer name
+                    .find(|column| !line[..*column].ends_with(|c: char| c.is_alphanumeric() || c == '_'))
+                    .map(|column| (line_index + 1, column + 1))
+            })
+            .unwrap_or((from + 1, 1))
+    }
+
+    /*
+      Finds the line and column of the first cell of a layout row, for error reporting. The rows are counted
+      inside the brackets after the layout: field, so one line layouts and names mentioning layout don't trip it up.
+    */
+    fn locate_row(source: &str, row: usize) -> (usize, usize) {
+        let masked = Wave::mask_literals(source);
+        let characters = || {
+            masked.lines().enumerate().flat_map(|(line_index, line)| {
+                line.chars().enumerate().map(move |(column, c)| (line_index + 1, column + 1, c))
+            })
+        };
+
+        // the field starts a line or follows the bracket or comma before it
+        let key = masked.lines().enumerate().find_map(|(line_index, line)| {
+            line.match_indices("layout").map(|(column, _)| column).find(|column| {
+                let before = line[..*column].trim_end();
+                let after = line[column + "layout".len()..].trim_start();
+                (before.is_empty() || before.ends_with(['(', ','])) && after.starts_with(':')
+            })
+            .map(|column| (line_index + 1, line[..column].chars().count() + 1))
+        });
+        let key = match key {
+            Some(key) => key,
+            None => return (1, 1),
+        };
+
+        let mut depth = 0;
+        let mut quotes = 0;
+        for (line, column, c) in characters().skip_while(|(line, column, _)| (*line, *column) < key) {
+            match c {
+                '[' => depth += 1,
+                ']' if depth == 1 => break,
+                ']' => depth -= 1,
+                // every row is a string, the even quotes open them
+                '"' if depth == 1 => {
+                    if quotes == row * 2 {
+                        return (line, column + 1);
+                    }
+                    quotes += 1;
+                }
+                _ => {}
+            }
+        }
+        key
+    }
+
+    /*
+      The source with the insides of strings, characters and comments blanked out,
+      everything else (the quotes included) stays on the same line and column
+    */
+    fn mask_literals(source: &str) -> String {
+        let mut masked = String::with_capacity(source.len());
+        let mut chars = source.chars().peekable();
+        // the quote that closes the literal being skipped
+        let mut closing = None;
+        let mut in_comment = false;
+        let blank = |c: char| if c == '\n' { '\n' } else { ' ' };
+
+        while let Some(c) = chars.next() {
+            match closing {
+                _ if c == '\n' => {
+                    in_comment = false;
+                    masked.push(c);
+                }
+                _ if in_comment => masked.push(' '),
+                Some(quote) if c == quote => {
+                    closing = None;
+                    masked.push(c);
+                }
+                Some(_) if c == '\\' => {
+                    masked.push(' ');
+                    if let Some(escaped) = chars.next() {
+                        masked.push(blank(escaped));
+                    }
+                }
+                Some(_) => masked.push(' '),
+                None if c == '"' || c == '\'' => {
+                    closing = Some(c);
+                    masked.push(c);
+                }
+                None if c == '/' && chars.peek() == Some(&'/') => {
+                    in_comment = true;
+                    masked.push(' ');
+                }
+                None => masked.push(c),
+            }
+        }
+        masked
+    }
+
+    pub fn cell(&self, row: usize, col: usize) -> Option<&WaveCell> {
+        self.legend.get(&self.layout[row][col])
+    }
+}
+
+#[derive(Default)]
+pub struct WaveLoader;
+
+impl AssetLoader for WaveLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let wave = Wave::parse(load_context.path(), bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(wave));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["wave"]
+    }
+}
+
+/*
+  One wave file per line, relative to the manifest. Lines starting with "//" are ignored.
+*/
+#[derive(Default)]
+pub struct WaveManifestLoader;
+
+impl AssetLoader for WaveManifestLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let directory = load_context.path().parent().map(Path::to_path_buf).unwrap_or_default();
+
+            let wave_paths: Vec<AssetPath<'static>> = std::str::from_utf8(bytes)?
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with("//"))
+                .map(|line| AssetPath::new(directory.join(line), None))
+                .collect();
+
+            let waves = wave_paths
+                .iter()
+                .map(|wave_path| load_context.get_handle(wave_path.clone()))
+                .collect();
+
+            load_context.set_default_asset(LoadedAsset::new(WaveManifest { waves }).with_dependencies(wave_paths));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["manifest"]
+    }
+}
+
+pub struct WavesPlugin;
+
+impl Plugin for WavesPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<Wave>()
+            .add_asset::<WaveManifest>()
+            .init_asset_loader::<WaveLoader>()
+            .init_asset_loader::<WaveManifestLoader>()
+            .add_startup_system(load_waves);
+    }
+}
+
+fn load_waves(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(Waves {
+        manifest: asset_server.load(WAVE_MANIFEST_PATH),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> Result<Wave, WaveParseError> {
+        Wave::parse(Path::new("test.wave"), source.as_bytes())
+    }
+
+    fn parse_error(source: &str) -> WaveParseError {
+        match parse(source) {
+            Ok(_) => panic!("expected the wave not to parse"),
+            Err(error) => error,
+        }
+    }
+
+    fn aliens(wave: &Wave) -> usize {
+        wave.layout.iter().flatten().filter(|cell| !EMPTY_CELLS.contains(cell)).count()
+    }
+
+    fn is_boss_wave(wave: &Wave) -> bool {
+        wave.layout.iter().flatten().any(|cell| *cell == 'd')
+    }
+
+    #[test]
+    fn the_same_seed_generates_the_same_waves() {
+        for index in 0..10 {
+            let first = WaveGenerator::new(42).generate(index);
+            let second = WaveGenerator::new(42).generate(index);
+
+            assert_eq!(first.layout, second.layout);
+            assert_eq!(first.speed, second.speed);
+            assert_eq!(first.fire_rate, second.fire_rate);
+            assert_eq!(first.entry, second.entry);
+        }
+    }
+
+    #[test]
+    fn different_seeds_generate_different_waves() {
+        let layouts = |seed| (0..10).map(|index| WaveGenerator::new(seed).generate(index).layout).collect::<Vec<_>>();
+
+        assert_ne!(layouts(1), layouts(2));
+    }
+
+    #[test]
+    fn generated_waves_get_harder() {
+        let generator = WaveGenerator::new(7);
+        let mut previous: Option<Wave> = None;
+        let mut previous_aliens = 0;
+        let mut previous_boss = 0;
+
+        for index in 0..40 {
+            let wave = generator.generate(index);
+
+            assert_eq!(is_boss_wave(&wave), index % WaveGenerator::BOSS_EVERY == WaveGenerator::BOSS_EVERY - 1);
+            if let Some(previous) = &previous {
+                assert!(wave.speed >= previous.speed, "wave {} is slower", index);
+                assert!(wave.fire_rate >= previous.fire_rate, "wave {} fires less", index);
+            }
+            // boss waves leave rows clear for the boss, and each boss is tougher than the last
+            if is_boss_wave(&wave) {
+                let boss = wave.legend[&'d'].health_for(100);
+                assert!(boss >= previous_boss, "the boss on wave {} is weaker", index);
+                previous_boss = boss;
+            } else {
+                assert!(aliens(&wave) >= previous_aliens, "wave {} has fewer aliens", index);
+                previous_aliens = aliens(&wave);
+            }
+
+            previous = Some(wave);
+        }
+    }
+
+    #[test]
+    fn parses_a_valid_wave() {
+        let wave = parse(
+            r####"#![enable(implicit_some)]
+(
+    name: "Valid",
+    speed: 1.5,
+    fire_rate: 0.5,
+    column_delay: 0.2,
+    legend: {
+        'A': (alien: "Aris", speed: 2.0, fire_rate: 0.5),
+    },
+    layout: ["aA#a"],
+    bunkers: (count: 4, shape: ["###"]),
+)"####,
+        )
+        .unwrap();
+
+        assert_eq!(wave.speed, 1.5);
+        assert_eq!(wave.column_delay, 0.2);
+        assert_eq!(wave.layout, vec![vec!['a', 'A', '#', 'a']]);
+    }
+
+    #[test]
+    fn rejects_a_zero_speed() {
+        let error = parse_error(
+            r####"#![enable(implicit_some)]
+(
+    name: "Stopped",
+    speed: 0.0,
+    layout: ["aa"],
+)"####,
+        );
+
+        assert_eq!((error.line, error.column), (4, 5));
+        assert!(error.message.contains("speed"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_a_negative_fire_rate() {
+        let error = parse_error(
+            r####"#![enable(implicit_some)]
+(
+    name: "Backwards",
+    fire_rate: -1.0,
+    layout: ["aa"],
+)"####,
+        );
+
+        assert_eq!((error.line, error.column), (4, 5));
+        assert!(error.message.contains("fire_rate"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_a_bad_legend_multiplier() {
+        let error = parse_error(
+            r####"#![enable(implicit_some)]
+(
+    name: "Legend",
+    speed: 1.0,
+    legend: {
+        'A': (alien: "Aris", speed: 1.0),
+        'B': (alien: "Aris", fire_rate: 0.0),
+    },
+    layout: ["AB"],
+)"####,
+        );
+
+        assert_eq!((error.line, error.column), (7, 30));
+        assert!(error.message.contains("fire_rate"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_a_negative_column_delay() {
+        let error = parse_error(
+            r####"#![enable(implicit_some)]
+(
+    name: "Early",
+    column_delay: -0.5,
+    layout: ["aa"],
+)"####,
+        );
+
+        assert_eq!((error.line, error.column), (4, 5));
+        assert!(error.message.contains("column_delay"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_no_bunkers_in_a_bunkers_block() {
+        let error = parse_error(
+            r####"#![enable(implicit_some)]
+(
+    name: "Bare",
+    layout: ["aa"],
+    bunkers: (
+        count: 0,
+        shape: ["###"],
+    ),
+)"####,
+        );
+
+        assert_eq!((error.line, error.column), (6, 9));
+        assert!(error.message.contains("count"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_bunkers_that_overlap() {
+        let error = parse_error(
+            r####"#![enable(implicit_some)]
+(
+    name: "Crowded",
+    layout: ["aa"],
+    bunkers: (
+        count: 30,
+        shape: ["###"],
+    ),
+)"####,
+        );
+
+        assert_eq!((error.line, error.column), (7, 9));
+        assert!(error.message.contains("don't fit"), "{}", error);
+    }
+
+    #[test]
+    fn finds_rows_in_a_one_line_layout() {
+        let error = parse_error(
+            r####"#![enable(implicit_some)]
+(
+    name: "Not the layout: you want",
+    intro: "layout",
+    layout: ["aa", "aZ"],
+)"####,
+        );
+
+        assert_eq!((error.line, error.column), (5, 22));
+        assert!(error.message.contains("'Z'"), "{}", error);
+    }
+
+    #[test]
+    fn finds_rows_past_comments_and_quotes() {
+        let error = parse_error(
+            r####"#![enable(implicit_some)]
+(
+    name: "Commented",
+    layout: [
+        // the "front" row, and ["a" decoy]
+        "aa",
+        "Za",
+    ],
+)"####,
+        );
+
+        assert_eq!((error.line, error.column), (7, 10));
+        assert!(error.message.contains("'Z'"), "{}", error);
     }
 }
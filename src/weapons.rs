@@ -0,0 +1,373 @@
+use bevy::{
+    asset::{AssetLoader, AssetPath, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::{BoxedFuture, HashSet},
+};
+use iyes_loopless::prelude::*;
+use serde::Deserialize;
+use std::{f32::consts::FRAC_PI_2, path::PathBuf};
+
+use crate::{
+    aliens::Alien,
+    player::{Player, Ship, ShipInputs},
+    shared::*,
+    simulation::{SimulationApp, SimulationPhase},
+    GameState, Paused,
+};
+
+pub const ARSENAL_PATH: &str = "weapons/ship.arsenal";
+// the extra bullets fired on each side while the spread shot power-up is active
+const SPREAD_SHOT_ANGLE: f32 = 15.0;
+// how much a torch grows while a charge shot is held
+const CHARGE_TORCH_GROWTH: f32 = 0.6;
+
+const WEAPON_HUD_FONT_SIZE: f32 = 20.0;
+const WEAPON_HUD_PADDING_TOP: Val = Val::Px(70.0); // under the power up hud
+const WEAPON_HUD_PADDING_LEFT: Val = Val::Px(345.0);
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum FireMode {
+    // one bullet straight up
+    Single,
+    // count bullets fanned evenly over spread degrees
+    Spread { count: u32, spread: f32 },
+    // passes through aliens, damaging each one once
+    Piercing,
+    // hold the trigger to charge, fires on release with up to max_multiplier the damage and size
+    Charge { charge_time: f32, max_multiplier: f32 },
+    // turns toward the nearest alien by at most turn_rate degrees per second
+    Homing { turn_rate: f32 },
+}
+
+/*
+  Arsenal files are a RON list of weapons, selected in game with the number keys:
+  [
+      (
+          name: "Single",
+          cooldown: 1.2,
+          sprite: "images/ferris_bullet.png",
+          size: (33.0, 70.0),
+          speed: 350.0,
+          damage: 1,
+          fire: Single,
+      ),
+  ]
+*/
+#[derive(Deserialize)]
+struct WeaponFile {
+    name: String,
+    cooldown: f32,
+    sprite: String,
+    size: (f32, f32),
+    speed: f32,
+    damage: u32,
+    fire: FireMode,
+}
+
+#[derive(Clone)]
+pub struct WeaponType {
+    pub name: String,
+    pub cooldown: f32,
+    pub sprite: Handle<Image>,
+    pub size: Vec2,
+    pub speed: f32,
+    pub damage: u32,
+    pub fire: FireMode,
+}
+
+impl WeaponType {
+    /*
+      Spawns the bullets for one shot, multiplier scales the damage and size (see FireMode::Charge)
+    */
+    pub fn fire(&self, commands: &mut Commands, ship: Entity, origin: Vec2, multiplier: f32, spread_shot: bool) {
+        let mut angles = match self.fire {
+            FireMode::Spread { count, spread } if count > 1 => (0..count)
+                .map(|i| -spread / 2.0 + spread * i as f32 / (count - 1) as f32)
+                .collect(),
+            _ => vec![0.0],
+        };
+        if spread_shot {
+            angles.extend([-SPREAD_SHOT_ANGLE, SPREAD_SHOT_ANGLE]);
+        }
+
+        let damage = ((self.damage as f32 * multiplier).round() as u32).max(1);
+
+        for angle in angles {
+            let mut bullet = commands.spawn();
+            bullet
+                .insert_bundle(BulletBundle::from_ship(
+                    origin,
+                    self.sprite.clone(),
+                    self.size * multiplier,
+                    self.speed,
+                    angle,
+                ))
+                .insert(Damage(damage))
+                .insert(FiredBy(ship));
+
+            match self.fire {
+                FireMode::Piercing => {
+                    bullet.insert(Piercing::default());
+                }
+                FireMode::Homing { turn_rate } => {
+                    bullet.insert(Homing { turn_rate });
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[derive(TypeUuid)]
+#[uuid = "b6d1f0a2-7c4e-4d39-8e15-3a9f2c7b6e41"]
+pub struct Arsenal {
+    pub weapons: Vec<WeaponType>,
+}
+
+pub struct Weapons {
+    arsenal: Handle<Arsenal>,
+}
+
+impl Weapons {
+    pub fn is_loaded(&self, arsenals: &Assets<Arsenal>) -> bool {
+        arsenals.get(&self.arsenal).is_some()
+    }
+
+    pub fn get<'a>(&self, index: usize, arsenals: &'a Assets<Arsenal>) -> Option<&'a WeaponType> {
+        arsenals.get(&self.arsenal)?.weapons.get(index)
+    }
+}
+
+/*
+  The weapon a torch fires, an index into the arsenal
+*/
+#[derive(Component, Default)]
+pub struct Weapon {
+    pub index: usize,
+    // 0 to 1, only used by FireMode::Charge
+    pub charge: f32,
+}
+
+impl Weapon {
+    /*
+      Returns the shot multiplier when the weapon should fire this step
+    */
+    pub fn trigger(&mut self, weapon_type: &WeaponType, pressed: bool) -> Option<f32> {
+        match weapon_type.fire {
+            FireMode::Charge { charge_time, max_multiplier } => {
+                if pressed {
+                    self.charge = (self.charge + TIME_STEP / charge_time).min(1.0);
+                    return None;
+                }
+                if self.charge == 0.0 {
+                    return None;
+                }
+
+                let multiplier = 1.0 + (max_multiplier - 1.0) * self.charge;
+                self.charge = 0.0;
+                Some(multiplier)
+            }
+            _ if pressed => Some(1.0),
+            _ => None,
+        }
+    }
+
+    pub fn torch_scale(&self) -> f32 {
+        1.0 + self.charge * CHARGE_TORCH_GROWTH
+    }
+}
+
+#[derive(Component, Deref)]
+pub struct Damage(pub u32);
+
+/*
+  The ship that fired a bullet, its player scores what the bullet destroys
+*/
+#[derive(Component, Deref)]
+pub struct FiredBy(pub Entity);
+
+/*
+  The aliens a piercing bullet has already damaged
+*/
+#[derive(Component, Default)]
+pub struct Piercing {
+    pub hits: HashSet<Entity>,
+}
+
+#[derive(Component)]
+pub struct Homing {
+    pub turn_rate: f32,
+}
+
+/*
+  The name of the weapon each ship has selected
+*/
+#[derive(Component)]
+pub struct WeaponHud;
+
+#[derive(Default)]
+pub struct ArsenalLoader;
+
+impl AssetLoader for ArsenalLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let weapon_files: Vec<WeaponFile> = ron::de::from_bytes(bytes)?;
+
+            // sprites are relative to the assets folder, like every other image
+            let sprite_paths: Vec<AssetPath<'static>> = weapon_files
+                .iter()
+                .map(|weapon| AssetPath::new(PathBuf::from(&weapon.sprite), None))
+                .collect();
+
+            let weapons = weapon_files
+                .into_iter()
+                .zip(sprite_paths.iter())
+                .map(|(weapon, sprite_path)| WeaponType {
+                    name: weapon.name,
+                    cooldown: weapon.cooldown,
+                    sprite: load_context.get_handle(sprite_path.clone()),
+                    size: Vec2::new(weapon.size.0, weapon.size.1),
+                    speed: weapon.speed,
+                    damage: weapon.damage,
+                    fire: weapon.fire,
+                })
+                .collect();
+
+            load_context.set_default_asset(LoadedAsset::new(Arsenal { weapons }).with_dependencies(sprite_paths));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["arsenal"]
+    }
+}
+
+pub struct WeaponsPlugin;
+
+impl Plugin for WeaponsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<Arsenal>()
+            .init_asset_loader::<ArsenalLoader>()
+            .add_simulation_system(SimulationPhase::Input, swap_weapons.run_in_state(GameState::Playing))
+            .add_simulation_system(SimulationPhase::Ai, steer_homing_bullets.run_in_state(GameState::Playing))
+            .add_simulation_system(SimulationPhase::Cleanup, update_weapon_hud.run_in_state(GameState::Playing))
+            .add_startup_system(load_arsenal)
+            .add_enter_system(GameState::Playing, spawn_weapon_hud.run_unless_resource_exists::<Paused>());
+    }
+}
+
+fn load_arsenal(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(Weapons {
+        arsenal: asset_server.load(ARSENAL_PATH),
+    });
+}
+
+fn swap_weapons(
+    ship_inputs: Res<ShipInputs>,
+    weapons: Res<Weapons>,
+    arsenals: Res<Assets<Arsenal>>,
+    ship_query: Query<(&Player, &Children), With<Ship>>,
+    mut weapon_query: Query<&mut Weapon>,
+) {
+    for (player, children) in ship_query.iter() {
+        let index = match ship_inputs.get(*player).weapon() {
+            Some(index) if weapons.get(index, &arsenals).is_some() => index,
+            _ => continue,
+        };
+
+        for child in children.iter() {
+            if let Ok(mut weapon) = weapon_query.get_mut(*child) {
+                weapon.index = index;
+                weapon.charge = 0.0;
+            }
+        }
+    }
+}
+
+fn spawn_weapon_hud(mut commands: Commands, asset_server: Res<AssetServer>, hud_query: Query<With<WeaponHud>>) {
+    if !hud_query.is_empty() {
+        return;
+    }
+
+    commands
+        .spawn()
+        .insert(WeaponHud)
+        .insert(Name::new("Weapon HUD"))
+        .insert_bundle(
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font_size: WEAPON_HUD_FONT_SIZE,
+                    color: SCORE_COLOR,
+                    font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    top: WEAPON_HUD_PADDING_TOP,
+                    left: WEAPON_HUD_PADDING_LEFT,
+                    ..default()
+                },
+                ..default()
+            }),
+        );
+}
+
+fn update_weapon_hud(
+    weapons: Res<Weapons>,
+    arsenals: Res<Assets<Arsenal>>,
+    ship_query: Query<(&Player, &Children), With<Ship>>,
+    weapon_query: Query<&Weapon>,
+    mut hud_query: Query<&mut Text, With<WeaponHud>>,
+) {
+    let mut hud = String::new();
+    let co_op = ship_query.iter().count() > 1;
+
+    for (player, children) in ship_query.iter() {
+        // both torches always carry the same weapon
+        let weapon_type = children
+            .iter()
+            .find_map(|child| weapon_query.get(*child).ok())
+            .and_then(|weapon| weapons.get(weapon.index, &arsenals));
+
+        if let Some(weapon_type) = weapon_type {
+            if co_op {
+                hud.push_str(&format!("{}: ", player.label()));
+            }
+            hud.push_str(&format!("{}  ", weapon_type.name));
+        }
+    }
+
+    for mut text in hud_query.iter_mut() {
+        text.sections[0].value = hud.clone();
+    }
+}
+
+fn steer_homing_bullets(
+    mut bullet_query: Query<(&mut Transform, &mut Velocity, &Homing), Without<Alien>>,
+    alien_query: Query<&Transform, With<Alien>>,
+) {
+    for (mut transform, mut velocity, homing) in bullet_query.iter_mut() {
+        let position = transform.translation.truncate();
+
+        let target = alien_query
+            .iter()
+            .map(|alien_transform| alien_transform.translation.truncate())
+            .min_by(|a, b| a.distance_squared(position).total_cmp(&b.distance_squared(position)));
+        let target = match target {
+            Some(target) => target,
+            None => continue,
+        };
+
+        velocity.0 = turn_towards(velocity.0, target - position, homing.turn_rate.to_radians() * TIME_STEP);
+        // bullet sprites point up
+        transform.rotation = Quat::from_rotation_z(velocity.y.atan2(velocity.x) - FRAC_PI_2);
+    }
+}